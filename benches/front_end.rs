@@ -0,0 +1,96 @@
+//! Counts the allocations made while scanning and parsing a large generated
+//! program, and times both. Run it with `cargo bench`.
+
+use rlox::parser::Parser;
+use rlox::scanner::Scanner;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// The system allocator, counting every allocation made through it.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const COPIES: usize = 2_000;
+
+const PROGRAM: &str = r#"
+class Shape {
+  init(name, sides) {
+    this.name = name;
+    this.sides = sides;
+  }
+
+  describe() {
+    return this.name + " has " + "some sides";
+  }
+}
+
+fun fibonacci(n) {
+  if (n <= 1) return n;
+  return fibonacci(n - 2) + fibonacci(n - 1);
+}
+
+var total = 0;
+for (var i = 0; i < 10; i = i + 1) {
+  total = total + fibonacci(i) * 2.5;
+}
+print Shape("square", 4).describe();
+"#;
+
+/// Runs `f` and returns what it allocated along with the time it took.
+fn measure<T>(f: impl FnOnce() -> T) -> (T, usize, usize, Duration) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = BYTES.load(Ordering::Relaxed);
+    let start = Instant::now();
+
+    let result = f();
+
+    let elapsed = start.elapsed();
+    (
+        result,
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        BYTES.load(Ordering::Relaxed) - bytes,
+        elapsed,
+    )
+}
+
+fn report(name: &str, allocations: usize, bytes: usize, elapsed: Duration) {
+    println!(
+        "{:<8} {:>10} allocations {:>12} bytes {:>10.2?}",
+        name, allocations, bytes, elapsed
+    );
+}
+
+fn main() {
+    let source = PROGRAM.repeat(COPIES);
+    println!("{} bytes of source", source.len());
+
+    let (tokens, allocations, bytes, elapsed) =
+        measure(|| Scanner::new(&source).filter_map(Result::ok).count());
+    report("scan", allocations, bytes, elapsed);
+    println!("{} tokens", tokens);
+
+    let (statements, allocations, bytes, elapsed) = measure(|| {
+        let tokens = Scanner::new(&source).filter_map(Result::ok);
+        Parser::new(tokens).parse().unwrap().statements.len()
+    });
+    report("parse", allocations, bytes, elapsed);
+    println!("{} statements", statements);
+}
@@ -0,0 +1,173 @@
+use crate::class::LoxInstance;
+use crate::environment::Environment;
+use crate::interpreter::{Interpreter, Interrupt, RuntimeError, Value};
+use crate::parser::{FunctionDecl, FunctionId};
+use crate::resolver::ResolvedAst;
+use crate::scanner::{Token, TokenKind};
+use crate::symbol::Symbol;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// Anything that can be called from Lox code with `callee(arguments)`.
+pub trait LoxCallable<'src>: fmt::Debug + fmt::Display {
+    /// Number of arguments the callable expects. The interpreter checks it
+    /// before calling `call`.
+    fn arity(&self) -> usize;
+
+    /// Takes an `Rc` so that callables can hand out references to themselves,
+    /// e.g. a class calling its initializer on a new instance. `paren` is the
+    /// closing parenthesis of the call, errors are reported there.
+    fn call(
+        self: Rc<Self>,
+        interpreter: &mut Interpreter<'src>,
+        paren: &Token<'src>,
+        arguments: Vec<Value<'src>>,
+    ) -> Result<Value<'src>, RuntimeError<'src>>;
+}
+
+/// A function declared in Lox code. It keeps the environment it was declared
+/// in alive so that it can be used as a closure, and the program it was
+/// declared in for its body.
+pub struct LoxFunction<'src> {
+    program: Rc<ResolvedAst<'src>>,
+    declaration: FunctionId,
+    closure: Rc<RefCell<Environment<'src>>>,
+    /// `init()` methods always return `this`.
+    is_initializer: bool,
+}
+
+impl<'src> LoxFunction<'src> {
+    pub fn new(
+        program: Rc<ResolvedAst<'src>>,
+        declaration: FunctionId,
+        closure: Rc<RefCell<Environment<'src>>>,
+        is_initializer: bool,
+    ) -> Self {
+        LoxFunction {
+            program,
+            declaration,
+            closure,
+            is_initializer,
+        }
+    }
+
+    /// Turns a method into a bound method: a function whose closure defines
+    /// `this` as the given instance.
+    pub fn bind(&self, instance: Rc<LoxInstance<'src>>) -> LoxFunction<'src> {
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        environment.define(Symbol::THIS, Value::Instance(instance));
+
+        LoxFunction::new(
+            Rc::clone(&self.program),
+            self.declaration,
+            Rc::new(RefCell::new(environment)),
+            self.is_initializer,
+        )
+    }
+
+    fn declaration(&self) -> &FunctionDecl<'src> {
+        &self.program.ast[self.declaration]
+    }
+
+    /// Only meaningful on bound methods.
+    fn this(&self) -> Result<Value<'src>, RuntimeError<'src>> {
+        let this = Token::new(TokenKind::This, "this", self.declaration().name.line);
+        self.closure.borrow().get_at(0, &this)
+    }
+}
+
+impl<'src> LoxCallable<'src> for LoxFunction<'src> {
+    fn arity(&self) -> usize {
+        self.declaration().params.len()
+    }
+
+    fn call(
+        self: Rc<Self>,
+        interpreter: &mut Interpreter<'src>,
+        _paren: &Token<'src>,
+        arguments: Vec<Value<'src>>,
+    ) -> Result<Value<'src>, RuntimeError<'src>> {
+        // Each call gets its own environment, so recursion works.
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        let declaration = self.declaration();
+        for (param, argument) in declaration.params.iter().zip(arguments) {
+            environment.define(param.symbol(), argument);
+        }
+
+        let environment = Rc::new(RefCell::new(environment));
+        match interpreter.execute_block(&self.program, &declaration.body, environment) {
+            // Even an early `return;` gives back `this` in an initializer.
+            Ok(()) | Err(Interrupt::Return(_)) if self.is_initializer => self.this(),
+            Ok(()) => Ok(Value::Nil),
+            Err(Interrupt::Return(value)) => Ok(value),
+            Err(Interrupt::Error(error)) => Err(error),
+        }
+    }
+}
+
+impl fmt::Display for LoxFunction<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<fn {}>", self.declaration().name.lexeme)
+    }
+}
+
+// The closure may hold the function itself, a derived implementation would
+// recurse forever.
+impl fmt::Debug for LoxFunction<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// Signature of the Rust functions exposed to Lox code. The arguments are
+/// already checked against the arity. An error message becomes a runtime
+/// error reported at the call site.
+pub type NativeFn<'src> = dyn Fn(&[Value<'src>]) -> Result<Value<'src>, String>;
+
+/// A function implemented in Rust, see `Interpreter::register_native`.
+pub struct NativeFunction<'src> {
+    name: String,
+    arity: usize,
+    function: Box<NativeFn<'src>>,
+}
+
+impl<'src> NativeFunction<'src> {
+    pub fn new<F>(name: &str, arity: usize, function: F) -> Self
+    where
+        F: Fn(&[Value<'src>]) -> Result<Value<'src>, String> + 'static,
+    {
+        NativeFunction {
+            name: name.to_owned(),
+            arity,
+            function: Box::new(function),
+        }
+    }
+}
+
+impl<'src> LoxCallable<'src> for NativeFunction<'src> {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(
+        self: Rc<Self>,
+        _interpreter: &mut Interpreter<'src>,
+        paren: &Token<'src>,
+        arguments: Vec<Value<'src>>,
+    ) -> Result<Value<'src>, RuntimeError<'src>> {
+        (self.function)(&arguments).map_err(|message| RuntimeError::new(paren, &message))
+    }
+}
+
+impl fmt::Display for NativeFunction<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn>")
+    }
+}
+
+impl fmt::Debug for NativeFunction<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
@@ -0,0 +1,124 @@
+use crate::callable::{LoxCallable, LoxFunction};
+use crate::interpreter::{Interpreter, RuntimeError, Value};
+use crate::scanner::Token;
+use crate::symbol::Symbol;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// A class declared in Lox code. Calling it creates a new instance.
+pub struct LoxClass<'src> {
+    pub name: Symbol,
+    superclass: Option<Rc<LoxClass<'src>>>,
+    methods: HashMap<Symbol, Rc<LoxFunction<'src>>>,
+}
+
+impl<'src> LoxClass<'src> {
+    pub fn new(
+        name: Symbol,
+        superclass: Option<Rc<LoxClass<'src>>>,
+        methods: HashMap<Symbol, Rc<LoxFunction<'src>>>,
+    ) -> Self {
+        LoxClass {
+            name,
+            superclass,
+            methods,
+        }
+    }
+
+    /// Looks for a method on the class, then up the inheritance chain.
+    pub fn find_method(&self, name: Symbol) -> Option<Rc<LoxFunction<'src>>> {
+        match self.methods.get(&name) {
+            Some(method) => Some(Rc::clone(method)),
+            None => self
+                .superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name)),
+        }
+    }
+}
+
+impl<'src> LoxCallable<'src> for LoxClass<'src> {
+    fn arity(&self) -> usize {
+        self.find_method(Symbol::INIT)
+            .map_or(0, |init| init.arity())
+    }
+
+    fn call(
+        self: Rc<Self>,
+        interpreter: &mut Interpreter<'src>,
+        paren: &Token<'src>,
+        arguments: Vec<Value<'src>>,
+    ) -> Result<Value<'src>, RuntimeError<'src>> {
+        let instance = Rc::new(LoxInstance::new(Rc::clone(&self)));
+
+        if let Some(init) = self.find_method(Symbol::INIT) {
+            Rc::new(init.bind(Rc::clone(&instance))).call(interpreter, paren, arguments)?;
+        }
+
+        Ok(Value::Instance(instance))
+    }
+}
+
+impl fmt::Display for LoxClass<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl fmt::Debug for LoxClass<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }
+}
+
+pub struct LoxInstance<'src> {
+    class: Rc<LoxClass<'src>>,
+    fields: RefCell<HashMap<Symbol, Value<'src>>>,
+}
+
+impl<'src> LoxInstance<'src> {
+    pub fn new(class: Rc<LoxClass<'src>>) -> Self {
+        LoxInstance {
+            class,
+            fields: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Fields shadow methods. Methods are bound to the instance they are
+    /// accessed from, hence the `Rc`.
+    pub fn get(
+        instance: &Rc<LoxInstance<'src>>,
+        name: &Token<'src>,
+    ) -> Result<Value<'src>, RuntimeError<'src>> {
+        if let Some(value) = instance.fields.borrow().get(&name.symbol()) {
+            return Ok(value.clone());
+        }
+
+        match instance.class.find_method(name.symbol()) {
+            Some(method) => Ok(Value::Callable(Rc::new(method.bind(Rc::clone(instance))))),
+            None => Err(RuntimeError::new(
+                name,
+                &format!("Undefined property '{}'.", name.lexeme),
+            )),
+        }
+    }
+
+    pub fn set(&self, name: &Token<'src>, value: Value<'src>) {
+        self.fields.borrow_mut().insert(name.symbol(), value);
+    }
+}
+
+impl fmt::Display for LoxInstance<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
+
+// Fields can point back to the instance itself, don't print them.
+impl fmt::Debug for LoxInstance<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{} instance>", self.class.name)
+    }
+}
@@ -0,0 +1,341 @@
+use crate::interpreter::RuntimeError;
+use crate::parser::ParseError;
+use crate::resolver::ResolveError;
+use crate::scanner::{ScanError, ScanErrorKind, Token};
+use crate::span::{SourceMap, Span};
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// An error ready to be shown to the user, rendered like rustc does:
+///
+/// ```text
+/// error: Unexpected character.
+///  --> 1:9
+///   |
+/// 1 | var a = @;
+///   |         ^ not valid in Lox code
+///   = help: ...
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    /// Part of the source to underline. Errors without a location only show
+    /// their message.
+    pub span: Option<Span>,
+    /// Line mentioned in a note when the span can't be shown.
+    pub line: Option<usize>,
+    /// Printed next to the underline.
+    pub label: Option<String>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: &str) -> Self {
+        Diagnostic {
+            message: message.to_owned(),
+            span: None,
+            line: None,
+            label: None,
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_line(mut self, line: usize) -> Self {
+        self.line = Some(line);
+        self
+    }
+
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = Some(label.to_owned());
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(note.to_owned());
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Self {
+        self.help = Some(help.to_owned());
+        self
+    }
+
+    /// Renders the diagnostic against the source it refers to. `color` adds
+    /// ANSI escape codes, only use it when writing to a terminal.
+    pub fn render(&self, source_map: &SourceMap, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{}{}{}", style, text, RESET)
+            } else {
+                text.to_owned()
+            }
+        };
+
+        let mut output = format!(
+            "{}{}\n",
+            paint(RED, "error"),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+
+        // Spans from another source, or in the middle of a char, can't be
+        // shown.
+        let location = self.span.and_then(|span| {
+            let (line, column) = source_map.line_column(span.start)?;
+            Some((span, line, column, source_map.line_at(span.start)?))
+        });
+        let width = location.map_or(0, |(_, line, _, _)| line.to_string().len());
+        let gutter = " ".repeat(width);
+
+        if let Some((span, line, column, text)) = location {
+            // The underline stops at the end of the line for multi-line
+            // spans, and empty spans still get a caret.
+            let before = column - 1;
+            let underline = source_map
+                .snippet(span)
+                .unwrap_or("")
+                .lines()
+                .next()
+                .map_or(0, |first| first.chars().count())
+                .min(text.chars().count().saturating_sub(before))
+                .max(1);
+
+            // Tabs are kept so that the caret lines up however wide the
+            // terminal shows them.
+            let indent: String = text
+                .chars()
+                .take(before)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+
+            let bar = paint(BLUE, "|");
+            output.push_str(&format!(
+                "{}{} {}:{}\n",
+                gutter,
+                paint(BLUE, "-->"),
+                line,
+                column
+            ));
+            output.push_str(&format!("{} {}\n", gutter, bar));
+            output.push_str(&format!(
+                "{} {} {}\n",
+                paint(BLUE, &line.to_string()),
+                bar,
+                text
+            ));
+
+            let mut marker = paint(RED, &"^".repeat(underline));
+            if let Some(label) = &self.label {
+                marker.push(' ');
+                marker.push_str(&paint(RED, label));
+            }
+            output.push_str(&format!("{} {} {}{}\n", gutter, bar, indent, marker));
+        }
+
+        let fallback = match (location, self.line) {
+            (None, Some(line)) => Some(format!("at line {}", line)),
+            _ => None,
+        };
+        let notes = fallback
+            .iter()
+            .chain(&self.notes)
+            .map(|note| ("note", note));
+        for (kind, text) in notes.chain(self.help.iter().map(|help| ("help", help))) {
+            output.push_str(&format!(
+                "{} {} {}: {}\n",
+                gutter,
+                paint(BLUE, "="),
+                paint(BOLD, kind),
+                text
+            ));
+        }
+
+        output
+    }
+}
+
+/// Tokens made up by the parser or the interpreter have no place in the
+/// source.
+fn token_span(token: &Token) -> Option<Span> {
+    if token.column == 0 {
+        None
+    } else {
+        Some(token.span)
+    }
+}
+
+impl From<&ScanError> for Diagnostic {
+    fn from(error: &ScanError) -> Self {
+        let diagnostic = Diagnostic::error(&error.message)
+            .with_span(error.span)
+            .with_line(error.line);
+
+        match error.kind {
+            ScanErrorKind::UnexpectedCharacter(_) => diagnostic.with_label("not valid in Lox code"),
+            ScanErrorKind::UnterminatedString => diagnostic
+                .with_label("string starts here")
+                .with_help("add a closing '\"'"),
+            ScanErrorKind::UnterminatedBlockComment => diagnostic
+                .with_label("comment starts here")
+                .with_help("add a closing '*/'"),
+            ScanErrorKind::UnknownEscape(_) => diagnostic
+                .with_label("unknown escape")
+                .with_help(r#"the escapes are \n \t \r \\ \" \0 and \u{XXXX}"#),
+            ScanErrorKind::InvalidUnicodeEscape => diagnostic.with_label("invalid escape"),
+            ScanErrorKind::MalformedNumber => diagnostic
+                .with_label("not a valid number")
+                .with_help("numbers look like 42, 1_000, 3.14, 1e-9, 0xFF or 0b1010"),
+            ScanErrorKind::InvalidCodePoint(_) => {
+                diagnostic.with_label("not a Unicode scalar value")
+            }
+        }
+    }
+}
+
+impl From<&ParseError<'_>> for Diagnostic {
+    fn from(error: &ParseError) -> Self {
+        let mut diagnostic = Diagnostic::error(&error.message).with_line(error.token.line);
+
+        if let Some(span) = token_span(&error.token) {
+            let expected: Vec<String> = error.expected.iter().map(|k| k.to_string()).collect();
+            let label = if expected.is_empty() {
+                format!("unexpected {}", error.token.kind)
+            } else {
+                format!(
+                    "expected {}, found {}",
+                    expected.join(" or "),
+                    error.token.kind
+                )
+            };
+            diagnostic = diagnostic.with_span(span).with_label(&label);
+        }
+
+        match error.context {
+            Some(context) => diagnostic.with_note(context),
+            None => diagnostic,
+        }
+    }
+}
+
+impl From<&ResolveError<'_>> for Diagnostic {
+    fn from(error: &ResolveError) -> Self {
+        let diagnostic = Diagnostic::error(&error.message).with_line(error.token.line);
+
+        match token_span(&error.token) {
+            Some(span) => diagnostic.with_span(span),
+            None => diagnostic,
+        }
+    }
+}
+
+impl From<&RuntimeError<'_>> for Diagnostic {
+    fn from(error: &RuntimeError) -> Self {
+        let diagnostic = Diagnostic::error(&error.message).with_line(error.token.line);
+
+        match token_span(&error.token) {
+            Some(span) => diagnostic.with_span(span),
+            None => diagnostic,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    #[test]
+    fn render_scan_error() {
+        let source = "var a = 1;\nvar b = @;\n";
+        let mut scanner = Scanner::new(source);
+        let errors = scanner.scan_tokens();
+
+        let rendered = Diagnostic::from(&errors[0]).render(&SourceMap::new(source), false);
+        assert_eq!(
+            "error: Unexpected character.\n \
+             --> 2:9\n  \
+             |\n\
+             2 | var b = @;\n  \
+             |         ^ not valid in Lox code\n",
+            rendered
+        );
+    }
+
+    #[test]
+    fn underline_whole_span() {
+        let source = "print \"abc";
+        let mut scanner = Scanner::new(source);
+        let errors = scanner.scan_tokens();
+
+        let rendered = Diagnostic::from(&errors[0]).render(&SourceMap::new(source), false);
+        assert!(rendered.contains("1 | print \"abc\n  |       ^^^^ string starts here\n"));
+        assert!(rendered.ends_with("  = help: add a closing '\"'\n"));
+    }
+
+    #[test]
+    fn parse_error_at_end() {
+        let source = "print 1";
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens();
+        let errors = Parser::new(scanner.tokens).parse().unwrap_err();
+
+        let rendered = Diagnostic::from(&errors[0]).render(&SourceMap::new(source), false);
+        assert!(rendered.ends_with("1 | print 1\n  |        ^ expected ';', found end of input\n"));
+    }
+
+    #[test]
+    fn indent_with_tabs() {
+        let source = "{\n\tprint\t@;\n}";
+        let mut scanner = Scanner::new(source);
+        let errors = scanner.scan_tokens();
+
+        let rendered = Diagnostic::from(&errors[0]).render(&SourceMap::new(source), false);
+        assert!(rendered.contains("2 | \tprint\t@;\n  | \t     \t^ not valid"));
+    }
+
+    #[test]
+    fn without_span() {
+        let diagnostic = Diagnostic::error("Something broke.").with_note("somewhere");
+        assert_eq!(
+            "error: Something broke.\n = note: somewhere\n",
+            diagnostic.render(&SourceMap::new(""), false)
+        );
+    }
+
+    #[test]
+    fn span_outside_source() {
+        let diagnostic = Diagnostic::error("Oops.")
+            .with_span(Span::new(17, 20))
+            .with_line(1);
+        assert_eq!(
+            "error: Oops.\n = note: at line 1\n",
+            diagnostic.render(&SourceMap::new("f();"), false)
+        );
+
+        // Inside 'é'.
+        let diagnostic = Diagnostic::error("Oops.").with_span(Span::new(1, 2));
+        assert_eq!(
+            "error: Oops.\n",
+            diagnostic.render(&SourceMap::new("é"), false)
+        );
+    }
+
+    #[test]
+    fn colors() {
+        let diagnostic = Diagnostic::error("Oops.").with_span(Span::new(0, 1));
+        let rendered = diagnostic.render(&SourceMap::new("x"), true);
+
+        assert!(rendered.starts_with("\x1b[1;31merror\x1b[0m"));
+        assert!(rendered.contains("\x1b[1;31m^\x1b[0m"));
+    }
+}
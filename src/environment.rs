@@ -0,0 +1,180 @@
+use crate::interpreter::{RuntimeError, Value};
+use crate::scanner::Token;
+use crate::symbol::Symbol;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Bindings of a single scope. Scopes are chained through `enclosing`, the
+/// outermost one holds the globals.
+#[derive(Debug, Default)]
+pub struct Environment<'src> {
+    values: HashMap<Symbol, Value<'src>>,
+    enclosing: Option<Rc<RefCell<Environment<'src>>>>,
+}
+
+impl<'src> Environment<'src> {
+    pub fn new() -> Self {
+        Environment::default()
+    }
+
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment<'src>>>) -> Self {
+        Environment {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    /// Binds a new variable. Redefining an existing variable is allowed and
+    /// simply overwrites it.
+    pub fn define(&mut self, name: Symbol, value: Value<'src>) {
+        self.values.insert(name, value);
+    }
+
+    pub fn get(&self, name: &Token<'src>) -> Result<Value<'src>, RuntimeError<'src>> {
+        if let Some(value) = self.values.get(&name.symbol()) {
+            return Ok(value.clone());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().get(name),
+            None => Err(undefined_variable(name)),
+        }
+    }
+
+    /// Variables defined in this scope only, in no particular order.
+    pub fn bindings(&self) -> impl Iterator<Item = (Symbol, &Value<'src>)> {
+        self.values.iter().map(|(name, value)| (*name, value))
+    }
+
+    /// Reads a variable from the scope `distance` levels up the chain. The
+    /// resolver already made sure the variable is declared there.
+    pub fn get_at(
+        &self,
+        distance: usize,
+        name: &Token<'src>,
+    ) -> Result<Value<'src>, RuntimeError<'src>> {
+        if distance == 0 {
+            return self
+                .values
+                .get(&name.symbol())
+                .cloned()
+                .ok_or_else(|| undefined_variable(name));
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().get_at(distance - 1, name),
+            None => Err(undefined_variable(name)),
+        }
+    }
+
+    pub fn assign_at(
+        &mut self,
+        distance: usize,
+        name: &Token<'src>,
+        value: Value<'src>,
+    ) -> Result<(), RuntimeError<'src>> {
+        if distance == 0 {
+            return match self.values.get_mut(&name.symbol()) {
+                Some(slot) => {
+                    *slot = value;
+                    Ok(())
+                }
+                None => Err(undefined_variable(name)),
+            };
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign_at(distance - 1, name, value),
+            None => Err(undefined_variable(name)),
+        }
+    }
+
+    /// Assigns to an existing variable. Unlike `define`, this never creates
+    /// a new binding.
+    pub fn assign(
+        &mut self,
+        name: &Token<'src>,
+        value: Value<'src>,
+    ) -> Result<(), RuntimeError<'src>> {
+        if let Some(slot) = self.values.get_mut(&name.symbol()) {
+            *slot = value;
+            return Ok(());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => Err(undefined_variable(name)),
+        }
+    }
+}
+
+fn undefined_variable<'src>(name: &Token<'src>) -> RuntimeError<'src> {
+    RuntimeError::new(name, &format!("Undefined variable '{}'.", name.lexeme))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::TokenKind;
+
+    fn identifier(name: &str) -> Token<'_> {
+        Token::new(TokenKind::Identifier(Symbol::intern(name)), name, 1)
+    }
+
+    #[test]
+    fn define_and_get() {
+        let mut environment = Environment::new();
+        environment.define(Symbol::intern("a"), Value::Number(1.0));
+        assert_eq!(Ok(Value::Number(1.0)), environment.get(&identifier("a")));
+    }
+
+    #[test]
+    fn undefined() {
+        let mut environment = Environment::new();
+        let error = environment.get(&identifier("a")).unwrap_err();
+        assert_eq!("Undefined variable 'a'.", error.message);
+
+        let error = environment
+            .assign(&identifier("a"), Value::Nil)
+            .unwrap_err();
+        assert_eq!("Undefined variable 'a'.", error.message);
+    }
+
+    #[test]
+    fn enclosing_scopes() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        globals
+            .borrow_mut()
+            .define(Symbol::intern("a"), Value::Number(1.0));
+
+        let mut local = Environment::with_enclosing(Rc::clone(&globals));
+        local.assign(&identifier("a"), Value::Number(2.0)).unwrap();
+
+        assert_eq!(Ok(Value::Number(2.0)), local.get(&identifier("a")));
+        assert_eq!(
+            Ok(Value::Number(2.0)),
+            globals.borrow().get(&identifier("a"))
+        );
+    }
+
+    #[test]
+    fn get_at_distance() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        globals
+            .borrow_mut()
+            .define(Symbol::intern("a"), Value::Number(1.0));
+
+        let mut local = Environment::with_enclosing(Rc::clone(&globals));
+        local.define(Symbol::intern("a"), Value::Number(2.0));
+
+        assert_eq!(Ok(Value::Number(2.0)), local.get_at(0, &identifier("a")));
+        assert_eq!(Ok(Value::Number(1.0)), local.get_at(1, &identifier("a")));
+
+        local
+            .assign_at(1, &identifier("a"), Value::Number(3.0))
+            .unwrap();
+        assert_eq!(Ok(Value::Number(2.0)), local.get_at(0, &identifier("a")));
+        assert_eq!(Ok(Value::Number(3.0)), local.get_at(1, &identifier("a")));
+    }
+}
@@ -0,0 +1,1164 @@
+use crate::callable::{LoxCallable, LoxFunction, NativeFunction};
+use crate::class::{LoxClass, LoxInstance};
+use crate::environment::Environment;
+use crate::parser::{ExprId, ExprKind, StmtId, StmtKind};
+use crate::resolver::ResolvedAst;
+use crate::scanner::{Token, TokenKind};
+use crate::symbol::Symbol;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Calls nested deeper than this raise a runtime error instead of
+/// overflowing the Rust stack. Each Lox call takes up to 25 KiB of it in
+/// debug builds, so the thread running the interpreter needs a large stack.
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+/// Runtime representation of a Lox value.
+#[derive(Debug, Clone)]
+pub enum Value<'src> {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(LoxString),
+    Callable(Rc<dyn LoxCallable<'src> + 'src>),
+    Class(Rc<LoxClass<'src>>),
+    Instance(Rc<LoxInstance<'src>>),
+}
+
+impl Value<'_> {
+    /// `false` and `nil` are falsey, everything else is truthy.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Nil => false,
+            Value::Bool(b) => *b,
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write_number(f, *n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Callable(c) => write!(f, "{}", c),
+            Value::Class(c) => write!(f, "{}", c),
+            Value::Instance(i) => write!(f, "{}", i),
+        }
+    }
+}
+
+/// Writes `n` like jlox does, with Java's `Double.toString` minus a
+/// trailing `.0`: large and small magnitudes use E notation.
+fn write_number(f: &mut fmt::Formatter, n: f64) -> fmt::Result {
+    if n.is_nan() {
+        return write!(f, "NaN");
+    }
+    if n.is_infinite() {
+        return write!(f, "{}Infinity", if n < 0.0 { "-" } else { "" });
+    }
+    if n == 0.0 || (1e-3..1e7).contains(&n.abs()) {
+        return write!(f, "{}", n);
+    }
+
+    // Java keeps at least one digit after the point of the mantissa.
+    let scientific = format!("{:e}", n);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let point = if mantissa.contains('.') { "" } else { ".0" };
+    write!(f, "{}{}E{}", mantissa, point, exponent)
+}
+
+impl PartialEq for Value<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(l), Value::Bool(r)) => l == r,
+            (Value::Number(l), Value::Number(r)) => l == r,
+            (Value::String(l), Value::String(r)) => l == r,
+            // Callables, classes and instances are only equal to themselves.
+            (Value::Callable(l), Value::Callable(r)) => Rc::ptr_eq(l, r),
+            (Value::Class(l), Value::Class(r)) => Rc::ptr_eq(l, r),
+            (Value::Instance(l), Value::Instance(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+}
+
+/// A Lox string. String literals are interned and compared by symbol, the
+/// strings built at runtime are not.
+#[derive(Debug, Clone)]
+pub enum LoxString {
+    Interned(Symbol),
+    Owned(Rc<str>),
+}
+
+impl LoxString {
+    pub fn as_str(&self) -> &str {
+        match self {
+            LoxString::Interned(symbol) => symbol.as_str(),
+            LoxString::Owned(text) => text,
+        }
+    }
+}
+
+impl From<&str> for LoxString {
+    fn from(text: &str) -> Self {
+        LoxString::Owned(text.into())
+    }
+}
+
+impl fmt::Display for LoxString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl PartialEq for LoxString {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (LoxString::Interned(l), LoxString::Interned(r)) => l == r,
+            _ => self.as_str() == other.as_str(),
+        }
+    }
+}
+
+/// Error raised while evaluating an expression. `token` is the operator (or
+/// other token) responsible for the error, it gives us the line to report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError<'src> {
+    pub token: Token<'src>,
+    pub message: String,
+}
+
+impl<'src> RuntimeError<'src> {
+    pub fn new(token: &Token<'src>, message: &str) -> Self {
+        RuntimeError {
+            token: token.clone(),
+            message: message.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}\n[line {}]", self.message, self.token.line)
+    }
+}
+
+impl std::error::Error for RuntimeError<'_> {}
+
+/// Reasons for the execution of statements to stop early. A `return` unwinds
+/// the stack up to the enclosing call just like an error does.
+#[derive(Debug)]
+pub(crate) enum Interrupt<'src> {
+    Return(Value<'src>),
+    Error(RuntimeError<'src>),
+}
+
+impl<'src> From<RuntimeError<'src>> for Interrupt<'src> {
+    fn from(error: RuntimeError<'src>) -> Self {
+        Interrupt::Error(error)
+    }
+}
+
+/// Tree-walking evaluator. Each `ExprKind` variant maps to a function that
+/// recursively evaluates its children.
+///
+/// Programs are run once they went through the `Resolver`, variables it did
+/// not resolve are looked up in the globals. Functions keep the program they
+/// were declared in alive.
+///
+/// Functions, classes and variables keep borrowing their names from the
+/// source, which has to outlive the interpreter.
+#[derive(Debug)]
+pub struct Interpreter<'src> {
+    /// Number of calls in progress.
+    depth: usize,
+    globals: Rc<RefCell<Environment<'src>>>,
+    /// Innermost scope. It starts as the global scope.
+    environment: Rc<RefCell<Environment<'src>>>,
+}
+
+impl Default for Interpreter<'_> {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+impl<'src> Interpreter<'src> {
+    pub fn new() -> Self {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+
+        let mut interpreter = Interpreter {
+            environment: Rc::clone(&globals),
+            globals,
+            depth: 0,
+        };
+
+        // Seconds since the UNIX epoch, like jlox.
+        interpreter.register_native("clock", 0, |_| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|_| "System clock is set before 1970.".to_owned())?;
+            Ok(Value::Number(now.as_secs_f64()))
+        });
+
+        interpreter
+    }
+
+    /// Exposes a Rust function to Lox code as a global. Registering a name
+    /// twice replaces the previous definition.
+    ///
+    /// ```
+    /// use rlox::interpreter::{Interpreter, Value};
+    ///
+    /// let mut interpreter = Interpreter::new();
+    /// interpreter.register_native("double", 1, |arguments| match arguments[0] {
+    ///     Value::Number(n) => Ok(Value::Number(n * 2.0)),
+    ///     _ => Err("Argument must be a number.".to_owned()),
+    /// });
+    /// ```
+    pub fn register_native<F>(&mut self, name: &str, arity: usize, function: F)
+    where
+        F: Fn(&[Value<'src>]) -> Result<Value<'src>, String> + 'static,
+    {
+        let native = NativeFunction::new(name, arity, function);
+        self.globals
+            .borrow_mut()
+            .define(Symbol::intern(name), Value::Callable(Rc::new(native)));
+    }
+
+    /// Names and values of the global variables, sorted by name.
+    pub fn globals(&self) -> Vec<(&str, Value<'src>)> {
+        let mut globals: Vec<(&str, Value<'src>)> = self
+            .globals
+            .borrow()
+            .bindings()
+            .map(|(name, value)| (name.as_str(), value.clone()))
+            .collect();
+        globals.sort_by_key(|(name, _)| *name);
+
+        globals
+    }
+
+    pub fn interpret(&mut self, program: &Rc<ResolvedAst<'src>>) -> Result<(), RuntimeError<'src>> {
+        for &statement in &program.ast.statements {
+            match self.execute(program, statement) {
+                Ok(()) => {}
+                // A `return` outside of a function stops the program.
+                Err(Interrupt::Return(_)) => return Ok(()),
+                Err(Interrupt::Error(error)) => return Err(error),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn execute(
+        &mut self,
+        program: &Rc<ResolvedAst<'src>>,
+        statement: StmtId,
+    ) -> Result<(), Interrupt<'src>> {
+        let ast = &program.ast;
+
+        match &ast[statement].kind {
+            StmtKind::Expression(expr) => {
+                self.evaluate(program, *expr)?;
+            }
+            StmtKind::Print(expr) => {
+                let value = self.evaluate(program, *expr)?;
+                println!("{}", value);
+            }
+            StmtKind::Var(name, initializer) => {
+                let value = match initializer {
+                    Some(expr) => self.evaluate(program, *expr)?,
+                    None => Value::Nil,
+                };
+                self.environment.borrow_mut().define(name.symbol(), value);
+            }
+            StmtKind::Block(statements) => {
+                let environment = Environment::with_enclosing(Rc::clone(&self.environment));
+                self.execute_block(program, statements, Rc::new(RefCell::new(environment)))?;
+            }
+            StmtKind::If(condition, then_branch, else_branch) => {
+                if self.evaluate(program, *condition)?.is_truthy() {
+                    self.execute(program, *then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(program, *else_branch)?;
+                }
+            }
+            StmtKind::While(condition, body) => {
+                while self.evaluate(program, *condition)?.is_truthy() {
+                    self.execute(program, *body)?;
+                }
+            }
+            StmtKind::Function(declaration) => {
+                let function = LoxFunction::new(
+                    Rc::clone(program),
+                    *declaration,
+                    Rc::clone(&self.environment),
+                    false,
+                );
+                self.environment.borrow_mut().define(
+                    ast[*declaration].name.symbol(),
+                    Value::Callable(Rc::new(function)),
+                );
+            }
+            StmtKind::Return(_, value) => {
+                let value = match value {
+                    Some(expr) => self.evaluate(program, *expr)?,
+                    None => Value::Nil,
+                };
+                return Err(Interrupt::Return(value));
+            }
+            StmtKind::Class(name, superclass, methods) => {
+                let superclass = match superclass {
+                    Some(expr) => match self.evaluate(program, *expr)? {
+                        Value::Class(class) => Some(class),
+                        _ => {
+                            let token = match &ast[*expr].kind {
+                                ExprKind::Variable(token) => token,
+                                _ => name,
+                            };
+                            let error = RuntimeError::new(token, "Superclass must be a class.");
+                            return Err(error.into());
+                        }
+                    },
+                    None => None,
+                };
+
+                self.environment
+                    .borrow_mut()
+                    .define(name.symbol(), Value::Nil);
+
+                // Methods of a subclass close over an extra scope holding
+                // `super`.
+                let enclosing = Rc::clone(&self.environment);
+                if let Some(superclass) = &superclass {
+                    let mut environment = Environment::with_enclosing(Rc::clone(&enclosing));
+                    environment.define(Symbol::SUPER, Value::Class(Rc::clone(superclass)));
+                    self.environment = Rc::new(RefCell::new(environment));
+                }
+
+                let mut class_methods = HashMap::new();
+                for &method in methods {
+                    let name = ast[method].name.symbol();
+                    let function = LoxFunction::new(
+                        Rc::clone(program),
+                        method,
+                        Rc::clone(&self.environment),
+                        name == Symbol::INIT,
+                    );
+                    class_methods.insert(name, Rc::new(function));
+                }
+
+                let class = LoxClass::new(name.symbol(), superclass, class_methods);
+                self.environment = enclosing;
+                self.environment
+                    .borrow_mut()
+                    .assign(name, Value::Class(Rc::new(class)))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Executes the statements in the given environment. The current one is
+    /// restored afterwards, even if a statement fails.
+    pub(crate) fn execute_block(
+        &mut self,
+        program: &Rc<ResolvedAst<'src>>,
+        statements: &[StmtId],
+        environment: Rc<RefCell<Environment<'src>>>,
+    ) -> Result<(), Interrupt<'src>> {
+        let previous = std::mem::replace(&mut self.environment, environment);
+        let result = statements
+            .iter()
+            .try_for_each(|&s| self.execute(program, s));
+        self.environment = previous;
+
+        result
+    }
+
+    /// Evaluates an expression of `program`.
+    pub fn evaluate(
+        &mut self,
+        program: &Rc<ResolvedAst<'src>>,
+        expr: ExprId,
+    ) -> Result<Value<'src>, RuntimeError<'src>> {
+        match &program.ast[expr].kind {
+            ExprKind::Literal(token) => Ok(self.literal(token)),
+            ExprKind::Grouping(inner) => self.evaluate(program, *inner),
+            ExprKind::Unary(operator, right) => self.unary(program, operator, *right),
+            ExprKind::Binary(left, operator, right) => {
+                self.binary(program, *left, operator, *right)
+            }
+            ExprKind::Variable(name) | ExprKind::This(name) => {
+                self.look_up_variable(name, program.depth(expr))
+            }
+            ExprKind::Assign(name, value) => {
+                let value = self.evaluate(program, *value)?;
+                match program.depth(expr) {
+                    Some(distance) => {
+                        self.environment
+                            .borrow_mut()
+                            .assign_at(distance, name, value.clone())?
+                    }
+                    None => self.globals.borrow_mut().assign(name, value.clone())?,
+                }
+                Ok(value)
+            }
+            ExprKind::Logical(left, operator, right) => {
+                self.logical(program, *left, operator, *right)
+            }
+            ExprKind::Call(callee, paren, arguments) => {
+                self.call(program, *callee, paren, arguments)
+            }
+            ExprKind::Get(object, name) => match self.evaluate(program, *object)? {
+                Value::Instance(instance) => LoxInstance::get(&instance, name),
+                _ => Err(RuntimeError::new(name, "Only instances have properties.")),
+            },
+            ExprKind::Set(object, name, value) => {
+                let instance = match self.evaluate(program, *object)? {
+                    Value::Instance(instance) => instance,
+                    _ => return Err(RuntimeError::new(name, "Only instances have fields.")),
+                };
+
+                let value = self.evaluate(program, *value)?;
+                instance.set(name, value.clone());
+                Ok(value)
+            }
+            ExprKind::Super(keyword, method) => {
+                self.super_method(keyword, method, program.depth(expr))
+            }
+        }
+    }
+
+    fn look_up_variable(
+        &self,
+        name: &Token<'src>,
+        depth: Option<usize>,
+    ) -> Result<Value<'src>, RuntimeError<'src>> {
+        match depth {
+            Some(distance) => self.environment.borrow().get_at(distance, name),
+            None => self.globals.borrow().get(name),
+        }
+    }
+
+    /// `super` is defined in the scope right above the one defining `this`.
+    fn super_method(
+        &self,
+        keyword: &Token<'src>,
+        method: &Token<'src>,
+        depth: Option<usize>,
+    ) -> Result<Value<'src>, RuntimeError<'src>> {
+        let distance = depth.expect("The resolver always resolves 'super'");
+
+        let superclass = match self.environment.borrow().get_at(distance, keyword)? {
+            Value::Class(class) => class,
+            _ => unreachable!("'super' is always bound to a class"),
+        };
+
+        let this = Token::new(TokenKind::This, "this", keyword.line);
+        let instance = match self.environment.borrow().get_at(distance - 1, &this)? {
+            Value::Instance(instance) => instance,
+            _ => unreachable!("'this' is always bound to an instance"),
+        };
+
+        match superclass.find_method(method.symbol()) {
+            Some(method) => Ok(Value::Callable(Rc::new(method.bind(instance)))),
+            None => Err(RuntimeError::new(
+                method,
+                &format!("Undefined property '{}'.", method.lexeme),
+            )),
+        }
+    }
+
+    fn call(
+        &mut self,
+        program: &Rc<ResolvedAst<'src>>,
+        callee: ExprId,
+        paren: &Token<'src>,
+        arguments: &[ExprId],
+    ) -> Result<Value<'src>, RuntimeError<'src>> {
+        let callee = self.evaluate(program, callee)?;
+
+        let mut values = Vec::with_capacity(arguments.len());
+        for &argument in arguments {
+            values.push(self.evaluate(program, argument)?);
+        }
+
+        let callable: Rc<dyn LoxCallable<'src> + 'src> = match callee {
+            Value::Callable(callable) => callable,
+            Value::Class(class) => class,
+            _ => {
+                return Err(RuntimeError::new(
+                    paren,
+                    "Can only call functions and classes.",
+                ))
+            }
+        };
+
+        if values.len() != callable.arity() {
+            let message = format!(
+                "Expected {} arguments but got {}.",
+                callable.arity(),
+                values.len()
+            );
+            return Err(RuntimeError::new(paren, &message));
+        }
+
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(RuntimeError::new(paren, "Stack overflow."));
+        }
+
+        self.depth += 1;
+        let result = callable.call(self, paren, values);
+        self.depth -= 1;
+
+        result
+    }
+
+    /// `and` and `or` short-circuit and return the last evaluated operand,
+    /// not a bool.
+    fn logical(
+        &mut self,
+        program: &Rc<ResolvedAst<'src>>,
+        left: ExprId,
+        operator: &Token<'src>,
+        right: ExprId,
+    ) -> Result<Value<'src>, RuntimeError<'src>> {
+        let left = self.evaluate(program, left)?;
+
+        let short_circuits = match operator.kind {
+            TokenKind::Or => left.is_truthy(),
+            TokenKind::And => !left.is_truthy(),
+            _ => unreachable!("Invalid logical operator"),
+        };
+
+        if short_circuits {
+            Ok(left)
+        } else {
+            self.evaluate(program, right)
+        }
+    }
+
+    fn literal(&self, token: &Token<'src>) -> Value<'src> {
+        match token.kind {
+            TokenKind::Nil => Value::Nil,
+            TokenKind::True => Value::Bool(true),
+            TokenKind::False => Value::Bool(false),
+            TokenKind::Number(n) => Value::Number(n),
+            TokenKind::String(symbol) => Value::String(LoxString::Interned(symbol)),
+            _ => unreachable!("The parser only builds literals from literal tokens"),
+        }
+    }
+
+    fn unary(
+        &mut self,
+        program: &Rc<ResolvedAst<'src>>,
+        operator: &Token<'src>,
+        right: ExprId,
+    ) -> Result<Value<'src>, RuntimeError<'src>> {
+        let right = self.evaluate(program, right)?;
+
+        match operator.kind {
+            TokenKind::Minus => match right {
+                Value::Number(n) => Ok(Value::Number(-n)),
+                _ => Err(RuntimeError::new(operator, "Operand must be a number.")),
+            },
+            TokenKind::Bang => Ok(Value::Bool(!right.is_truthy())),
+            _ => unreachable!("Invalid unary operator"),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        program: &Rc<ResolvedAst<'src>>,
+        left: ExprId,
+        operator: &Token<'src>,
+        right: ExprId,
+    ) -> Result<Value<'src>, RuntimeError<'src>> {
+        let left = self.evaluate(program, left)?;
+        let right = self.evaluate(program, right)?;
+
+        match operator.kind {
+            // Equality works on any pair of values. Values of different types
+            // are never equal.
+            TokenKind::EqualEqual => Ok(Value::Bool(left == right)),
+            TokenKind::BangEqual => Ok(Value::Bool(left != right)),
+
+            // '+' is overloaded for string concatenation.
+            TokenKind::Plus => match (left, right) {
+                (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
+                (Value::String(l), Value::String(r)) => Ok(Value::String(LoxString::Owned(
+                    [l.as_str(), r.as_str()].concat().into(),
+                ))),
+                _ => Err(RuntimeError::new(
+                    operator,
+                    "Operands must be two numbers or two strings.",
+                )),
+            },
+
+            _ => {
+                let (l, r) = match (left, right) {
+                    (Value::Number(l), Value::Number(r)) => (l, r),
+                    _ => return Err(RuntimeError::new(operator, "Operands must be numbers.")),
+                };
+
+                let value = match operator.kind {
+                    TokenKind::Minus => Value::Number(l - r),
+                    TokenKind::Star => Value::Number(l * r),
+                    TokenKind::Slash => Value::Number(l / r),
+                    TokenKind::Greater => Value::Bool(l > r),
+                    TokenKind::GreaterEqual => Value::Bool(l >= r),
+                    TokenKind::Less => Value::Bool(l < r),
+                    TokenKind::LessEqual => Value::Bool(l <= r),
+                    _ => unreachable!("Invalid binary operator"),
+                };
+
+                Ok(value)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::resolver::Resolver;
+    use crate::scanner::Scanner;
+    use std::thread;
+
+    fn parse(source: &str) -> Rc<ResolvedAst<'_>> {
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens();
+        let ast = Parser::new(scanner.tokens).parse().unwrap();
+
+        Rc::new(Resolver::new().resolve(ast).unwrap())
+    }
+
+    /// Values can borrow from the source, which is leaked to outlive them.
+    fn statement(source: &str) -> &'static str {
+        Box::leak(format!("{};", source).into_boxed_str())
+    }
+
+    /// Evaluates the single expression `source` with the given interpreter.
+    fn evaluate_with(
+        interpreter: &mut Interpreter<'static>,
+        source: &str,
+    ) -> Result<Value<'static>, RuntimeError<'static>> {
+        let program = parse(statement(source));
+        let expr = match program.ast.statements.as_slice() {
+            &[statement] => match program.ast[statement].kind {
+                StmtKind::Expression(expr) => expr,
+                _ => panic!("Expected a single expression"),
+            },
+            _ => panic!("Expected a single expression"),
+        };
+
+        interpreter.evaluate(&program, expr)
+    }
+
+    fn try_evaluate(source: &str) -> Result<Value<'static>, RuntimeError<'static>> {
+        evaluate_with(&mut Interpreter::new(), source)
+    }
+
+    fn evaluate(source: &str) -> Value<'static> {
+        try_evaluate(source).unwrap()
+    }
+
+    /// Runs `program` then evaluates `expr` with the resulting globals.
+    fn run_and_evaluate(program: &'static str, expr: &str) -> Value<'static> {
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(&parse(program)).unwrap();
+
+        evaluate_with(&mut interpreter, expr).unwrap()
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(Value::Number(11.0), evaluate("3 + 4 * 2"));
+        assert_eq!(Value::Number(14.0), evaluate("(3 + 4) * 2"));
+        assert_eq!(Value::Number(-2.5), evaluate("-5 / 2"));
+    }
+
+    #[test]
+    fn double_precision() {
+        assert_eq!(Value::Number(16777217.0), evaluate("16777216 + 1"));
+        assert_eq!("1.6777217E7", evaluate("0x1000001").to_string());
+    }
+
+    #[test]
+    fn number_display() {
+        let display = |n: f64| Value::Number(n).to_string();
+        assert_eq!("3", display(3.0));
+        assert_eq!("-2.5", display(-2.5));
+        assert_eq!("-0", display(-0.0));
+        assert_eq!("0.001", display(0.001));
+        assert_eq!("9999999", display(9999999.0));
+        assert_eq!("1.0E7", display(1e7));
+        assert_eq!("1.0E23", display(1e23));
+        assert_eq!("-1.5E-5", display(-1.5e-5));
+        assert_eq!("1.0E-4", display(0.0001));
+        assert_eq!("Infinity", display(f64::INFINITY));
+        assert_eq!("-Infinity", display(f64::NEG_INFINITY));
+        assert_eq!("NaN", display(f64::NAN));
+        assert_eq!("Infinity", evaluate("1 / 0").to_string());
+    }
+
+    #[test]
+    fn string_concatenation() {
+        assert_eq!(Value::String("ab".into()), evaluate(r#""a" + "b""#));
+    }
+
+    #[test]
+    fn comparison() {
+        assert_eq!(Value::Bool(true), evaluate("1 < 2"));
+        assert_eq!(Value::Bool(false), evaluate("1 >= 2"));
+    }
+
+    #[test]
+    fn equality() {
+        assert_eq!(Value::Bool(true), evaluate("nil == nil"));
+        assert_eq!(Value::Bool(false), evaluate(r#"1 == "1""#));
+        assert_eq!(Value::Bool(true), evaluate(r#""a" != "b""#));
+    }
+
+    #[test]
+    fn string_equality() {
+        // Literals are interned, concatenations are not.
+        assert_eq!(Value::Bool(true), evaluate(r#""ab" == "ab""#));
+        assert_eq!(Value::Bool(true), evaluate(r#""ab" == "a" + "b""#));
+        assert_eq!(Value::Bool(true), evaluate(r#""a" + "b" == "a" + "b""#));
+        assert_eq!(Value::Bool(false), evaluate(r#""ab" == "a" + "c""#));
+    }
+
+    #[test]
+    fn truthiness() {
+        assert_eq!(Value::Bool(true), evaluate("!nil"));
+        assert_eq!(Value::Bool(false), evaluate("!0"));
+        assert_eq!(Value::Bool(false), evaluate(r#"!"""#));
+        assert_eq!(Value::Bool(true), evaluate("!false"));
+    }
+
+    #[test]
+    fn operands_must_be_numbers() {
+        let error = try_evaluate(r#""a" * 2"#).unwrap_err();
+        assert_eq!(TokenKind::Star, error.token.kind);
+        assert_eq!("Operands must be numbers.", error.message);
+        assert_eq!("Operands must be numbers.\n[line 1]", error.to_string());
+    }
+
+    #[test]
+    fn operand_must_be_a_number() {
+        let error = try_evaluate("\n-\"abc\"").unwrap_err();
+        assert_eq!(TokenKind::Minus, error.token.kind);
+        assert_eq!(2, error.token.line);
+        assert_eq!("Operand must be a number.", error.message);
+    }
+
+    #[test]
+    fn mixed_addition() {
+        let error = try_evaluate(r#"1 + "a""#).unwrap_err();
+        assert_eq!(
+            "Operands must be two numbers or two strings.",
+            error.message
+        );
+    }
+
+    #[test]
+    fn statements() {
+        let statements = parse("print 1 + 2; \"a\" + \"b\";");
+        assert!(Interpreter::new().interpret(&statements).is_ok());
+    }
+
+    #[test]
+    fn error_in_statement() {
+        let statements = parse("print 1;\nprint -nil;\nprint 3;");
+        let error = Interpreter::new().interpret(&statements).unwrap_err();
+        assert_eq!(2, error.token.line);
+    }
+
+    #[test]
+    fn global_variables() {
+        assert_eq!(
+            Value::Number(3.0),
+            run_and_evaluate("var a = 1; var b = 2;", "a + b")
+        );
+        assert_eq!(Value::Nil, run_and_evaluate("var a;", "a"));
+        assert_eq!(
+            Value::Number(2.0),
+            run_and_evaluate("var a = 1; a = 2;", "a")
+        );
+    }
+
+    #[test]
+    fn blocks_and_shadowing() {
+        let program = r#"
+            var a = "global";
+            var b = "global";
+            {
+                var a = "shadowed";
+                b = a;
+            }
+        "#;
+
+        assert_eq!(
+            Value::String("global".into()),
+            run_and_evaluate(program, "a")
+        );
+        assert_eq!(
+            Value::String("shadowed".into()),
+            run_and_evaluate(program, "b")
+        );
+    }
+
+    #[test]
+    fn undefined_variable() {
+        let error = Interpreter::new()
+            .interpret(&parse("{ var a = 1; }\nprint a;"))
+            .unwrap_err();
+        assert_eq!("Undefined variable 'a'.", error.message);
+        assert_eq!(2, error.token.line);
+    }
+
+    #[test]
+    fn scope_is_restored_after_error() {
+        let mut interpreter = Interpreter::new();
+        let result = interpreter.interpret(&parse("var a = 1; { var a = 2; -nil; }"));
+        assert!(result.is_err());
+
+        assert_eq!(Ok(Value::Number(1.0)), evaluate_with(&mut interpreter, "a"));
+    }
+
+    #[test]
+    fn logical_operators() {
+        assert_eq!(Value::String("a".into()), evaluate(r#""a" or "b""#));
+        assert_eq!(Value::String("b".into()), evaluate(r#"nil or "b""#));
+        assert_eq!(Value::Nil, evaluate(r#"nil and "b""#));
+        assert_eq!(Value::String("b".into()), evaluate(r#"1 and "b""#));
+    }
+
+    #[test]
+    fn short_circuit() {
+        // The right-hand side would fail if it was evaluated.
+        assert_eq!(Value::Bool(true), evaluate("true or -nil"));
+        assert_eq!(Value::Bool(false), evaluate("false and -nil"));
+    }
+
+    #[test]
+    fn if_else() {
+        let program = "var a; if (1 > 2) a = 1; else a = 2;";
+        assert_eq!(Value::Number(2.0), run_and_evaluate(program, "a"));
+
+        let program = "var a = 0; if (nil) a = 1;";
+        assert_eq!(Value::Number(0.0), run_and_evaluate(program, "a"));
+    }
+
+    #[test]
+    fn while_loop() {
+        let program = "var i = 0; while (i < 10) i = i + 1;";
+        assert_eq!(Value::Number(10.0), run_and_evaluate(program, "i"));
+    }
+
+    #[test]
+    fn for_loop() {
+        let program = r#"
+            var a = 0;
+            var b = 1;
+            for (var i = 0; i < 10; i = i + 1) {
+                var temp = a;
+                a = b;
+                b = temp + b;
+            }
+        "#;
+
+        assert_eq!(Value::Number(55.0), run_and_evaluate(program, "a"));
+        // The loop variable is scoped to the loop.
+        let error = Interpreter::new()
+            .interpret(&parse("for (var i = 0; i < 1; i = i + 1) {} print i;"))
+            .unwrap_err();
+        assert_eq!("Undefined variable 'i'.", error.message);
+    }
+
+    #[test]
+    fn function_call() {
+        let program = r#"
+            fun add(a, b) {
+                return a + b;
+            }
+            var result = add(1, 2);
+        "#;
+
+        assert_eq!(Value::Number(3.0), run_and_evaluate(program, "result"));
+    }
+
+    #[test]
+    fn implicit_nil_return() {
+        let program = "fun noop() {} var result = noop();";
+        assert_eq!(Value::Nil, run_and_evaluate(program, "result"));
+    }
+
+    #[test]
+    fn recursion() {
+        let program = r#"
+            fun fib(n) {
+                if (n < 2) return n;
+                return fib(n - 2) + fib(n - 1);
+            }
+        "#;
+
+        assert_eq!(Value::Number(55.0), run_and_evaluate(program, "fib(10)"));
+    }
+
+    #[test]
+    fn closures() {
+        let program = r#"
+            fun makeCounter() {
+                var i = 0;
+                fun count() {
+                    i = i + 1;
+                    return i;
+                }
+                return count;
+            }
+            var counter = makeCounter();
+            counter();
+            counter();
+        "#;
+
+        assert_eq!(Value::Number(3.0), run_and_evaluate(program, "counter()"));
+    }
+
+    #[test]
+    fn print_function() {
+        let value = run_and_evaluate("fun f() {}", "f");
+        assert_eq!("<fn f>", value.to_string());
+    }
+
+    #[test]
+    fn arity_mismatch() {
+        let error = Interpreter::new()
+            .interpret(&parse("fun f(a) {}\nf(1, 2);"))
+            .unwrap_err();
+        assert_eq!("Expected 1 arguments but got 2.", error.message);
+        assert_eq!(2, error.token.line);
+    }
+
+    #[test]
+    fn call_non_callable() {
+        let error = Interpreter::new()
+            .interpret(&parse(r#""not a function"();"#))
+            .unwrap_err();
+        assert_eq!("Can only call functions and classes.", error.message);
+    }
+
+    #[test]
+    fn closures_capture_at_declaration() {
+        let program = r#"
+            var a = "global";
+            var first;
+            var second;
+            {
+                fun showA() {
+                    return a;
+                }
+
+                first = showA();
+                var a = "block";
+                second = showA();
+            }
+        "#;
+
+        assert_eq!(
+            Value::String("global".into()),
+            run_and_evaluate(program, "first")
+        );
+        assert_eq!(
+            Value::String("global".into()),
+            run_and_evaluate(program, "second")
+        );
+    }
+
+    #[test]
+    fn fields() {
+        let program = r#"
+            class Point {}
+            var point = Point();
+            point.x = 1;
+            point.y = 2;
+        "#;
+
+        assert_eq!(
+            Value::Number(3.0),
+            run_and_evaluate(program, "point.x + point.y")
+        );
+        assert_eq!(
+            "Point instance",
+            run_and_evaluate(program, "point").to_string()
+        );
+        assert_eq!("Point", run_and_evaluate(program, "Point").to_string());
+    }
+
+    #[test]
+    fn methods_and_this() {
+        let program = r#"
+            class Counter {
+                init(start) {
+                    this.count = start;
+                }
+
+                increment() {
+                    this.count = this.count + 1;
+                    return this;
+                }
+            }
+
+            var counter = Counter(10);
+            var increment = counter.increment;
+            increment();
+            counter.increment().increment();
+        "#;
+
+        assert_eq!(
+            Value::Number(13.0),
+            run_and_evaluate(program, "counter.count")
+        );
+    }
+
+    #[test]
+    fn initializer_returns_this() {
+        let program = r#"
+            class Foo {
+                init() {
+                    this.bar = 1;
+                    return;
+                }
+            }
+            var foo = Foo();
+            var same = foo.init();
+        "#;
+
+        assert_eq!(Value::Bool(true), run_and_evaluate(program, "foo == same"));
+    }
+
+    #[test]
+    fn inheritance_and_super() {
+        let program = r#"
+            class A {
+                name() {
+                    return "A";
+                }
+
+                describe() {
+                    return "I am " + this.name();
+                }
+            }
+
+            class B < A {
+                name() {
+                    return "B then " + super.name();
+                }
+            }
+
+            class C < B {}
+        "#;
+
+        assert_eq!(
+            Value::String("I am B then A".into()),
+            run_and_evaluate(program, "C().describe()")
+        );
+    }
+
+    #[test]
+    fn class_errors() {
+        let error = Interpreter::new()
+            .interpret(&parse("var NotAClass = 1; class A < NotAClass {}"))
+            .unwrap_err();
+        assert_eq!("Superclass must be a class.", error.message);
+
+        let error = Interpreter::new()
+            .interpret(&parse("class A {} A().missing;"))
+            .unwrap_err();
+        assert_eq!("Undefined property 'missing'.", error.message);
+
+        let error = Interpreter::new()
+            .interpret(&parse("var a = 1; a.field = 2;"))
+            .unwrap_err();
+        assert_eq!("Only instances have fields.", error.message);
+
+        let error = Interpreter::new()
+            .interpret(&parse("class A { init(a) {} } A();"))
+            .unwrap_err();
+        assert_eq!("Expected 1 arguments but got 0.", error.message);
+    }
+
+    #[test]
+    fn clock() {
+        // Seconds since the epoch, so well past 2020.
+        assert!(matches!(run_and_evaluate("", "clock()"), Value::Number(n) if n > 1.6e9));
+        assert_eq!("<native fn>", run_and_evaluate("", "clock").to_string());
+    }
+
+    #[test]
+    fn register_native() {
+        let mut interpreter = Interpreter::new();
+        interpreter.register_native("max", 2, |arguments| match arguments {
+            [Value::Number(a), Value::Number(b)] => Ok(Value::Number(a.max(*b))),
+            _ => Err("Arguments must be numbers.".to_owned()),
+        });
+
+        interpreter
+            .interpret(&parse("var result = max(1, 2);"))
+            .unwrap();
+        assert_eq!(
+            Ok(Value::Number(2.0)),
+            evaluate_with(&mut interpreter, "result")
+        );
+
+        let error = interpreter.interpret(&parse("\nmax(1, nil);")).unwrap_err();
+        assert_eq!("Arguments must be numbers.", error.message);
+        assert_eq!(2, error.token.line);
+
+        let error = interpreter.interpret(&parse("max(1);")).unwrap_err();
+        assert_eq!("Expected 2 arguments but got 1.", error.message);
+    }
+
+    #[test]
+    fn list_globals() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .interpret(&parse("var b = 2; var a = 1;"))
+            .unwrap();
+
+        let names: Vec<&str> = interpreter.globals().into_iter().map(|(n, _)| n).collect();
+        assert_eq!(vec!["a", "b", "clock"], names);
+    }
+
+    #[test]
+    fn stack_overflow() {
+        // Test threads only get 2 MiB of stack.
+        let thread = thread::Builder::new().stack_size(64 * 1024 * 1024);
+        let (message, depth) = thread
+            .spawn(|| {
+                let mut interpreter = Interpreter::new();
+                let error = interpreter
+                    .interpret(&parse("fun f() { f(); } f();"))
+                    .unwrap_err();
+                (error.message, interpreter.depth)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!("Stack overflow.", message);
+        assert_eq!(0, depth);
+    }
+}
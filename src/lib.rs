@@ -1,3 +1,11 @@
-pub mod scanner;
-pub mod parser;
+pub mod callable;
+pub mod class;
+pub mod diagnostics;
+pub mod environment;
 pub mod interpreter;
+pub mod parser;
+pub mod resolver;
+pub mod scanner;
+pub mod span;
+pub mod symbol;
+pub mod visitor;
@@ -1,33 +1,355 @@
-use rlox::parser;
-use rlox::parser::Parser;
-use rlox::scanner::Scanner;
+use rlox::diagnostics::Diagnostic;
+use rlox::interpreter::Interpreter;
+use rlox::parser::{Ast, ParseError, Parser, Stmt, StmtKind};
+use rlox::resolver::Resolver;
+use rlox::scanner::{ScanError, Scanner, StreamScanner, Token};
+use rlox::span::SourceMap;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Editor, Helper};
 use std::env;
-use std::fs;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, IsTerminal, Read};
+use std::path::PathBuf;
+use std::process;
+use std::rc::Rc;
+use std::thread;
+
+const HISTORY_FILE: &str = ".rlox_history";
+
+/// Lox calls recurse on the Rust stack, this leaves room for
+/// `interpreter::MAX_CALL_DEPTH` of them even in debug builds.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+const HELP: &str = "\
+Enter Lox statements or expressions. The value of an expression is printed.
+Input spanning several lines is completed until braces, parentheses and
+strings are closed.
+
+Commands:
+  :help    Show this message
+  :reset   Forget every variable, function and class
+  :env     List the global variables
+Press Ctrl-D to quit.";
+
+/// How errors are printed on stderr.
+#[derive(Clone, Copy)]
+enum ErrorFormat {
+    /// One line per error, like jlox: `[line 1] Error at 'x': message`.
+    Jlox,
+    /// The source line with the faulty part underlined, see `Diagnostic`.
+    /// Turned on by `--pretty`.
+    Pretty,
+}
+
+/// Why a piece of source could not be run. The errors themselves are already
+/// reported when this is returned.
+enum Failure {
+    /// Scanner, parser or resolver errors. Nothing was executed.
+    Static,
+    Runtime,
+}
 
 fn main() {
-    match env::args().nth(1) {
-        Some(arg) => run_file(arg),
-        None => start_interactive_mode()
+    let interpreter = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(|| {
+            let mut args: Vec<String> = env::args().skip(1).collect();
+            let format = match args.iter().position(|arg| arg == "--pretty") {
+                Some(index) => {
+                    args.remove(index);
+                    ErrorFormat::Pretty
+                }
+                None => ErrorFormat::Jlox,
+            };
+
+            match args.as_slice() {
+                [] => start_interactive_mode(format),
+                [script] => run_file(script, format),
+                _ => {
+                    eprintln!("Usage: rlox [--pretty] [script | -]");
+                    process::exit(64);
+                }
+            }
+        })
+        .expect("Could not start the interpreter thread");
+
+    // A panic was already printed by the thread.
+    if interpreter.join().is_err() {
+        process::exit(101);
+    }
+}
+
+/// Line editor helper. It only validates the input: pressing enter on
+/// incomplete input starts a new line instead of submitting it.
+struct LoxHelper;
+
+impl Validator for LoxHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let input = ctx.input();
+
+        if !input.starts_with(':') && Scanner::is_incomplete(input) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Completer for LoxHelper {
+    type Candidate = String;
+}
+
+impl Hinter for LoxHelper {
+    type Hint = String;
+}
+
+impl Highlighter for LoxHelper {}
+
+impl Helper for LoxHelper {}
+
+/// Reads and runs one input at a time. The interpreter is kept between
+/// inputs so that globals survive, and errors are reported without leaving
+/// the REPL.
+fn start_interactive_mode(format: ErrorFormat) {
+    let mut editor: Editor<LoxHelper, DefaultHistory> =
+        Editor::new().expect("Something went wrong setting up the terminal");
+    editor.set_helper(Some(LoxHelper));
+
+    let history = history_path();
+    if let Some(path) = &history {
+        // There is no history the first time.
+        let _ = editor.load_history(path);
+    }
+
+    while let Session::Reset = run_session(&mut editor, format) {}
+
+    if let Some(path) = &history {
+        if let Err(error) = editor.save_history(path) {
+            eprintln!("Could not save the history: {}", error);
+        }
+    }
+}
+
+/// How a REPL session ended.
+enum Session {
+    Reset,
+    Quit,
+}
+
+/// Runs inputs with a fresh interpreter until `:reset` or the end of the
+/// input. Functions, classes and variables keep borrowing their names from
+/// the inputs, which are kept in the source map until the session ends.
+fn run_session(editor: &mut Editor<LoxHelper, DefaultHistory>, format: ErrorFormat) -> Session {
+    let source_map = SourceMap::default();
+    let mut interpreter = Interpreter::new();
+
+    loop {
+        let input = match editor.readline("> ") {
+            Ok(input) => input,
+            // Ctrl-C drops the current input.
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => return Session::Quit,
+            Err(error) => {
+                eprintln!("Something went wrong reading the input: {}", error);
+                return Session::Quit;
+            }
+        };
+
+        if input.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(input.as_str());
+
+        match input.trim() {
+            ":help" => println!("{}", HELP),
+            ":reset" => return Session::Reset,
+            ":env" => {
+                for (name, value) in interpreter.globals() {
+                    println!("{} = {}", name, value);
+                }
+            }
+            command if command.starts_with(':') => {
+                eprintln!("Unknown command '{}'. Try :help.", command)
+            }
+            // Errors are already reported, just move on to the next input.
+            _ => {
+                let _ = run(&mut interpreter, &source_map, input, format);
+            }
+        }
+    }
+}
+
+/// The history lives in the home directory, if we can find it.
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+/// Runs a script, `-` being stdin. It is parsed as it is read.
+fn run_file(filename: &str, format: ErrorFormat) {
+    let reader: Box<dyn Read> = if filename == "-" {
+        Box::new(io::stdin())
+    } else {
+        match File::open(filename) {
+            Ok(file) => Box::new(file),
+            Err(error) => {
+                eprintln!("Could not open {}: {}", filename, error);
+                process::exit(66);
+            }
+        }
     };
+    let source_map = SourceMap::default();
+    let mut interpreter = Interpreter::new();
+
+    let mut scanner = StreamScanner::new(reader, &source_map);
+    let mut scan_errors = Vec::new();
+    let parsed = Parser::new(tokens(&mut scanner, &mut scan_errors)).parse();
+    if let Some(error) = scanner.take_error() {
+        eprintln!("Something went wrong reading {}: {}", filename, error);
+        process::exit(74);
+    }
+
+    let result = check(&source_map, &scan_errors, parsed, format)
+        .and_then(|ast| execute(&mut interpreter, &source_map, ast, false, format));
 
-    println!("Done.");
+    match result {
+        Ok(()) => {}
+        Err(Failure::Static) => process::exit(65),
+        Err(Failure::Runtime) => process::exit(70),
+    }
 }
 
-fn start_interactive_mode() {
+/// Runs a REPL input with the given interpreter, after adding it to the
+/// source map of what the interpreter ran before. A single expression (with
+/// or without its semicolon) gets its value printed.
+fn run<'src>(
+    interpreter: &mut Interpreter<'src>,
+    source_map: &'src SourceMap,
+    source: String,
+    format: ErrorFormat,
+) -> Result<(), Failure> {
+    let (start, source) = source_map.add(source, 1);
+    let mut scan_errors = Vec::new();
+    let scanner = Scanner::starting_at(source, start, 1);
+    let parsed = Parser::new(tokens(scanner, &mut scan_errors)).parse();
+    let parsed = match parsed {
+        // Maybe a lone expression, missing its semicolon.
+        Err(errors) if scan_errors.is_empty() => {
+            let scanner = Scanner::starting_at(source, start, 1);
+            match Parser::new(tokens(scanner, &mut Vec::new())).parse_expression() {
+                Ok((mut ast, expr)) => {
+                    let span = ast[expr].span;
+                    let statement = ast.add_stmt(Stmt::new(StmtKind::Expression(expr), span));
+                    ast.statements.push(statement);
+                    Ok(ast)
+                }
+                Err(_) => Err(errors),
+            }
+        }
+        parsed => parsed,
+    };
+
+    let ast = check(source_map, &scan_errors, parsed, format)?;
+    execute(interpreter, source_map, ast, true, format)
+}
+
+/// Reports the scan or parse errors, if any.
+fn check<'src>(
+    source_map: &SourceMap,
+    scan_errors: &[ScanError],
+    parsed: Result<Ast<'src>, Vec<ParseError<'src>>>,
+    format: ErrorFormat,
+) -> Result<Ast<'src>, Failure> {
+    // Parse errors caused by a bad token would only be noise.
+    if !scan_errors.is_empty() {
+        report(source_map, scan_errors, format);
+        return Err(Failure::Static);
+    }
+
+    parsed.map_err(|errors| {
+        report(source_map, &errors, format);
+        Failure::Static
+    })
+}
+
+/// Resolves and runs `ast`. In interactive mode, the value of a program made
+/// of a single expression is printed.
+fn execute<'src>(
+    interpreter: &mut Interpreter<'src>,
+    source_map: &SourceMap,
+    ast: Ast<'src>,
+    interactive: bool,
+    format: ErrorFormat,
+) -> Result<(), Failure> {
+    let program = match Resolver::new().resolve(ast) {
+        Ok(program) => Rc::new(program),
+        Err(errors) => {
+            report(source_map, &errors, format);
+            return Err(Failure::Static);
+        }
+    };
+
+    let single_expression = match program.ast.statements.as_slice() {
+        &[statement] => match program.ast[statement].kind {
+            StmtKind::Expression(expr) => Some(expr),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    let result = match single_expression {
+        Some(expr) if interactive => interpreter
+            .evaluate(&program, expr)
+            .map(|value| println!("{}", value)),
+        _ => interpreter.interpret(&program),
+    };
 
+    result.map_err(|error| {
+        report(source_map, &[error], format);
+        Failure::Runtime
+    })
 }
 
-fn run_file(filename: String) {
-    let contents = fs::read_to_string(filename).expect("Something went wrong reading the file");
-    run(&contents);
+/// Tokens of `scanner`, scanned as the parser asks for them. Scan errors are
+/// set aside in `errors`.
+fn tokens<'src, 'a>(
+    scanner: impl Iterator<Item = Result<Token<'src>, ScanError>> + 'a,
+    errors: &'a mut Vec<ScanError>,
+) -> impl Iterator<Item = Token<'src>> + 'a
+where
+    'src: 'a,
+{
+    scanner.filter_map(move |item| match item {
+        Ok(token) => Some(token),
+        Err(error) => {
+            errors.push(error);
+            None
+        }
+    })
 }
 
-fn run(source: &String) {
-    let mut scanner = Scanner::new(source.to_owned());
-    scanner.scan_tokens();
-    let mut parser = Parser::new(scanner.tokens);
-    let expr = parser.parse().unwrap();
-    let ast_dump = parser::ast_dump(&expr);
+/// Prints the errors on stderr. Pretty errors are in color if stderr is a
+/// terminal and `NO_COLOR` isn't set. It is stderr that matters, not
+/// stdout: errors go there, and colors must stay out of a redirected stderr
+/// even when the program's output is shown on the terminal.
+fn report<E>(source_map: &SourceMap, errors: &[E], format: ErrorFormat)
+where
+    E: fmt::Display,
+    for<'e> Diagnostic: From<&'e E>,
+{
+    let color = io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none();
 
-    println!("{}", ast_dump);
+    for error in errors {
+        match format {
+            ErrorFormat::Jlox => eprintln!("{}", error),
+            ErrorFormat::Pretty => eprint!("{}", Diagnostic::from(error).render(source_map, color)),
+        }
+    }
 }
@@ -1,48 +1,380 @@
+// Errors only happen once per statement, carrying them around unboxed is
+// fine.
+#![allow(clippy::result_large_err)]
+
 use crate::scanner::{Token, TokenKind};
+use crate::span::Span;
+use crate::symbol::Symbol;
+use crate::visitor::ExprVisitor;
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+/// Maximum number of arguments a function can take.
+const MAX_ARGUMENTS: usize = 255;
 
+/// Stands for any identifier in the kinds of tokens expected by the parser,
+/// only the kind is compared, not the name.
+const IDENTIFIER: TokenKind = TokenKind::Identifier(Symbol::EMPTY);
+
+/// Syntax error. The messages are the ones of the reference implementation,
+/// so that its test suite can be used.
 #[derive(PartialEq, Debug, Clone)]
-pub enum ParseError {
-    MissingParenthesis,
-    UnexpectedToken,
+pub struct ParseError<'src> {
+    /// The token the parser choked on.
+    pub token: Token<'src>,
+    /// Tokens that would have been accepted instead. Left empty when too
+    /// many tokens would fit, like at the start of an expression.
+    pub expected: Vec<TokenKind>,
+    /// E.g. "Expect ')' after expression."
+    pub message: String,
+    /// Part of the grammar being parsed, e.g. "in function parameters".
+    pub context: Option<&'static str>,
+}
+
+impl fmt::Display for ParseError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.token.kind == TokenKind::Eof {
+            write!(
+                f,
+                "[line {}] Error at end: {}",
+                self.token.line, self.message
+            )
+        } else {
+            write!(
+                f,
+                "[line {}] Error at '{}': {}",
+                self.token.line, self.token.lexeme, self.message
+            )
+        }
+    }
+}
+
+impl std::error::Error for ParseError<'_> {}
+
+/// An expression and the part of the source it was parsed from.
+#[derive(PartialEq, Debug)]
+pub struct Expr<'src> {
+    pub kind: ExprKind<'src>,
+    pub span: Span,
+}
+
+impl<'src> Expr<'src> {
+    pub fn new(kind: ExprKind<'src>, span: Span) -> Self {
+        Expr { kind, span }
+    }
+}
+
+/// Children are referred to by their id in the `Ast`.
+#[derive(PartialEq, Debug)]
+pub enum ExprKind<'src> {
+    Literal(Token<'src>),
+    Unary(Token<'src>, ExprId),
+    Binary(ExprId, Token<'src>, ExprId),
+    Grouping(ExprId),
+    Variable(Token<'src>),
+    Assign(Token<'src>, ExprId),
+    Logical(ExprId, Token<'src>, ExprId),
+    /// The token is the closing parenthesis, used to report errors.
+    Call(ExprId, Token<'src>, Vec<ExprId>),
+    Get(ExprId, Token<'src>),
+    Set(ExprId, Token<'src>, ExprId),
+    This(Token<'src>),
+    /// The `super` keyword then the method name.
+    Super(Token<'src>, Token<'src>),
+}
+
+/// A statement and the part of the source it was parsed from, including its
+/// trailing semicolon.
+#[derive(PartialEq, Debug)]
+pub struct Stmt<'src> {
+    pub kind: StmtKind<'src>,
+    pub span: Span,
+}
+
+impl<'src> Stmt<'src> {
+    pub fn new(kind: StmtKind<'src>, span: Span) -> Self {
+        Stmt { kind, span }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub enum StmtKind<'src> {
+    Expression(ExprId),
+    Print(ExprId),
+    Var(Token<'src>, Option<ExprId>),
+    Block(Vec<StmtId>),
+    If(ExprId, StmtId, Option<StmtId>),
+    While(ExprId, StmtId),
+    Function(FunctionId),
+    Return(Token<'src>, Option<ExprId>),
+    /// The superclass, if any, is always an `ExprKind::Variable`.
+    Class(Token<'src>, Option<ExprId>, Vec<FunctionId>),
 }
 
-// The tokens are owned. Probably not the best idea.
+/// Functions and methods. They are kept apart from the statements since
+/// methods aren't statements of their own.
 #[derive(PartialEq, Debug)]
-pub enum Expr {
-    Literal(Token),
-    Unary(Token, Box<Expr>),
-    Binary(Box<Expr>, Token, Box<Expr>),
-    Grouping(Box<Expr>),
+pub struct FunctionDecl<'src> {
+    pub name: Token<'src>,
+    pub params: Vec<Token<'src>>,
+    pub body: Vec<StmtId>,
+    /// From the name to the closing brace of the body.
+    pub span: Span,
+}
+
+/// Index of an expression in its `Ast`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ExprId(u32);
+
+/// Index of a statement in its `Ast`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct StmtId(u32);
+
+/// Index of a function declaration in its `Ast`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct FunctionId(u32);
+
+impl ExprId {
+    /// Expressions are numbered from 0, in the order they were added. Side
+    /// tables can be plain vectors indexed by it.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl StmtId {
+    /// See `ExprId::index`.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl FunctionId {
+    /// See `ExprId::index`.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Arena holding every node of a parsed program. Nodes refer to their
+/// children by id, so passes over the tree can keep their results in vectors
+/// indexed by id instead of mutating it, and the whole tree is freed at once.
+///
+/// Ids say nothing about where a node is in the tree: an assignment reuses
+/// the node of its target, which comes before the value. Passes go from the
+/// root statements down instead of in id order.
+#[derive(PartialEq, Debug, Default)]
+pub struct Ast<'src> {
+    exprs: Vec<Expr<'src>>,
+    stmts: Vec<Stmt<'src>>,
+    functions: Vec<FunctionDecl<'src>>,
+    /// The top-level statements, in order.
+    pub statements: Vec<StmtId>,
+}
+
+impl<'src> Ast<'src> {
+    pub fn new() -> Self {
+        Ast::default()
+    }
+
+    pub fn add_expr(&mut self, expr: Expr<'src>) -> ExprId {
+        self.exprs.push(expr);
+        ExprId(self.exprs.len() as u32 - 1)
+    }
+
+    pub fn add_stmt(&mut self, stmt: Stmt<'src>) -> StmtId {
+        self.stmts.push(stmt);
+        StmtId(self.stmts.len() as u32 - 1)
+    }
+
+    pub fn add_function(&mut self, function: FunctionDecl<'src>) -> FunctionId {
+        self.functions.push(function);
+        FunctionId(self.functions.len() as u32 - 1)
+    }
+
+    /// Number of expressions, the length of a side table covering them all.
+    pub fn expr_count(&self) -> usize {
+        self.exprs.len()
+    }
+
+    pub fn stmt_count(&self) -> usize {
+        self.stmts.len()
+    }
+}
+
+impl<'src> Index<ExprId> for Ast<'src> {
+    type Output = Expr<'src>;
+
+    fn index(&self, id: ExprId) -> &Expr<'src> {
+        &self.exprs[id.index()]
+    }
+}
+
+impl<'src> Index<StmtId> for Ast<'src> {
+    type Output = Stmt<'src>;
+
+    fn index(&self, id: StmtId) -> &Stmt<'src> {
+        &self.stmts[id.index()]
+    }
 }
 
-pub struct Parser {
-    tokens: Vec<Token>,
-    current: usize,
+impl<'src> Index<FunctionId> for Ast<'src> {
+    type Output = FunctionDecl<'src>;
+
+    fn index(&self, id: FunctionId) -> &FunctionDecl<'src> {
+        &self.functions[id.index()]
+    }
+}
+
+/// Nodes can be replaced in place by rewriting passes, see
+/// `visitor::VisitorMut`.
+impl IndexMut<ExprId> for Ast<'_> {
+    fn index_mut(&mut self, id: ExprId) -> &mut Self::Output {
+        &mut self.exprs[id.index()]
+    }
+}
+
+impl IndexMut<StmtId> for Ast<'_> {
+    fn index_mut(&mut self, id: StmtId) -> &mut Self::Output {
+        &mut self.stmts[id.index()]
+    }
+}
+
+impl IndexMut<FunctionId> for Ast<'_> {
+    fn index_mut(&mut self, id: FunctionId) -> &mut Self::Output {
+        &mut self.functions[id.index()]
+    }
+}
+
+/// Pulls its tokens one at a time from any iterator, a `Scanner` or a
+/// `Vec<Token>`, only looking one token ahead. Tokens don't need to be all
+/// scanned before parsing starts.
+pub struct Parser<'src, I = std::vec::IntoIter<Token<'src>>> {
+    tokens: I,
+    /// The lookahead: next token to be consumed.
+    current: Token<'src>,
+    previous: Token<'src>,
+    /// Nodes parsed so far.
+    ast: Ast<'src>,
+    errors: Vec<ParseError<'src>>,
+    context: Option<&'static str>,
 }
 
 /// Grammar we want to parse:
 ///
-///    expression     → equality ;
+///    program        → declaration* EOF ;
+///    declaration    → classDecl
+///                   | funDecl
+///                   | varDecl
+///                   | statement ;
+///    classDecl      → "class" IDENTIFIER ( "<" IDENTIFIER )?
+///                     "{" function* "}" ;
+///    funDecl        → "fun" function ;
+///    function       → IDENTIFIER "(" parameters? ")" block ;
+///    parameters     → IDENTIFIER ( "," IDENTIFIER )* ;
+///    varDecl        → "var" IDENTIFIER ( "=" expression )? ";" ;
+///    statement      → exprStmt
+///                   | forStmt
+///                   | ifStmt
+///                   | printStmt
+///                   | returnStmt
+///                   | whileStmt
+///                   | block ;
+///    exprStmt       → expression ";" ;
+///    forStmt        → "for" "(" ( varDecl | exprStmt | ";" )
+///                     expression? ";"
+///                     expression? ")" statement ;
+///    ifStmt         → "if" "(" expression ")" statement
+///                     ( "else" statement )? ;
+///    printStmt      → "print" expression ";" ;
+///    returnStmt     → "return" expression? ";" ;
+///    whileStmt      → "while" "(" expression ")" statement ;
+///    block          → "{" declaration* "}" ;
+///
+///    expression     → assignment ;
+///    assignment     → ( call "." )? IDENTIFIER "=" assignment
+///                   | logic_or ;
+///    logic_or       → logic_and ( "or" logic_and )* ;
+///    logic_and      → equality ( "and" equality )* ;
 ///    equality       → comparison ( ( "!=" | "==" ) comparison )* ;
 ///    comparison     → addition ( ( ">" | ">=" | "<" | "<=" ) addition )* ;
 ///    addition       → multiplication ( ( "-" | "+" ) multiplication )* ;
 ///    multiplication → unary ( ( "/" | "*" ) unary )* ;
 ///    unary          → ( "!" | "-" ) unary
-///                   | primary ;
-///    primary        → NUMBER | STRING | "false" | "true" | "nil"
-///                   | "(" expression ")" ;
+///                   | call ;
+///    call           → primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
+///    arguments      → expression ( "," expression )* ;
+///    primary        → NUMBER | STRING | "false" | "true" | "nil" | "this"
+///                   | "(" expression ")" | IDENTIFIER
+///                   | "super" "." IDENTIFIER ;
 ///
 /// Each rule is mapped to the corresponding function.
-impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+impl<'src, I: Iterator<Item = Token<'src>>> Parser<'src, I> {
+    pub fn new(tokens: impl IntoIterator<Item = Token<'src>, IntoIter = I>) -> Self {
+        let mut tokens = tokens.into_iter();
+        let current = tokens
+            .next()
+            .unwrap_or_else(|| Token::new(TokenKind::Eof, "", 1));
+
         Parser {
-            tokens: tokens,
-            current: 0,
+            tokens,
+            previous: current.clone(),
+            current,
+            ast: Ast::new(),
+            errors: Vec::new(),
+            context: None,
         }
     }
 
-    pub fn parse(&mut self) -> Result<Expr, ParseError> {
-        self.expression()
+    /// Parses the whole program. Parsing goes on after an error so that
+    /// every error is reported, see `parse_partial`.
+    pub fn parse(&mut self) -> Result<Ast<'src>, Vec<ParseError<'src>>> {
+        let (ast, errors) = self.parse_partial();
+
+        if errors.is_empty() {
+            Ok(ast)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Parses the whole program and returns every error found along the
+    /// way. After an error, the parser skips to the next statement: the
+    /// statements it did manage to parse are returned.
+    pub fn parse_partial(&mut self) -> (Ast<'src>, Vec<ParseError<'src>>) {
+        while !self.is_at_end() {
+            if let Some(statement) = self.declaration() {
+                self.ast.statements.push(statement);
+            }
+        }
+
+        (
+            std::mem::take(&mut self.ast),
+            std::mem::take(&mut self.errors),
+        )
+    }
+
+    /// Parses the tokens as a single expression, without the trailing
+    /// semicolon of an expression statement. Used by the REPL to evaluate
+    /// bare expressions. The `Ast` has no top-level statements, the
+    /// expression is returned along with it.
+    pub fn parse_expression(&mut self) -> Result<(Ast<'src>, ExprId), Vec<ParseError<'src>>> {
+        let result = self.expression().and_then(|expr| {
+            if self.is_at_end() {
+                Ok(expr)
+            } else {
+                Err(self.error("Expect end of expression.", &[TokenKind::Eof]))
+            }
+        });
+
+        match result {
+            Ok(expr) if self.errors.is_empty() => Ok((std::mem::take(&mut self.ast), expr)),
+            Ok(_) => Err(std::mem::take(&mut self.errors)),
+            Err(error) => {
+                self.errors.push(error);
+                Err(std::mem::take(&mut self.errors))
+            }
+        }
     }
 
     // Utilities
@@ -51,30 +383,72 @@ impl Parser {
         self.peek().kind == TokenKind::Eof
     }
 
-    fn peek(&self) -> Token {
-        self.tokens.get(self.current).unwrap().clone()
+    fn peek(&self) -> &Token<'src> {
+        &self.current
     }
 
-    fn previous(&self) -> Token {
-        self.tokens.get(self.current - 1).unwrap().clone()
+    fn previous(&self) -> &Token<'src> {
+        &self.previous
     }
 
-    fn advance(&mut self) -> Token {
+    /// Consumes the lookahead and pulls the next token. Tokens stop at `Eof`,
+    /// which is made up if the iterator ends without one.
+    fn advance(&mut self) -> Token<'src> {
         if !self.is_at_end() {
-            self.current = self.current + 1;
+            let next = self.tokens.next().unwrap_or_else(|| Token {
+                kind: TokenKind::Eof,
+                lexeme: "",
+                line: self.current.line,
+                column: 0,
+                span: Span::new(self.current.span.end, self.current.span.end),
+            });
+            self.previous = std::mem::replace(&mut self.current, next);
         }
 
-        self.previous()
+        self.previous.clone()
     }
 
-    fn consume(&mut self, kind: TokenKind) -> Option<Token> {
-        if self.peek().kind == kind {
-            Some(self.advance())
+    /// The value carried by `kind`, if any, is ignored.
+    fn consume(&mut self, kind: TokenKind, message: &str) -> Result<Token<'src>, ParseError<'src>> {
+        if std::mem::discriminant(&self.peek().kind) == std::mem::discriminant(&kind) {
+            Ok(self.advance())
         } else {
-            None
+            Err(self.error(message, &[kind]))
         }
     }
 
+    /// Error at the current token.
+    fn error(&self, message: &str, expected: &[TokenKind]) -> ParseError<'src> {
+        self.error_at(self.peek(), message, expected)
+    }
+
+    fn error_at(
+        &self,
+        token: &Token<'src>,
+        message: &str,
+        expected: &[TokenKind],
+    ) -> ParseError<'src> {
+        ParseError {
+            token: token.clone(),
+            expected: expected.to_vec(),
+            message: message.to_owned(),
+            context: self.context,
+        }
+    }
+
+    /// Runs `parse` with `context` describing the part of the grammar being
+    /// parsed. Errors raised meanwhile mention it.
+    fn in_context<T>(
+        &mut self,
+        context: &'static str,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError<'src>>,
+    ) -> Result<T, ParseError<'src>> {
+        let enclosing = self.context.replace(context);
+        let result = parse(self);
+        self.context = enclosing;
+        result
+    }
+
     fn match_any_of(&mut self, kinds: &[TokenKind]) -> bool {
         if kinds.contains(&self.peek().kind) {
             self.advance();
@@ -84,26 +458,386 @@ impl Parser {
         }
     }
 
+    /// Span from `start` to the end of the last consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous().span)
+    }
+
+    fn expr(&mut self, kind: ExprKind<'src>, span: Span) -> ExprId {
+        self.ast.add_expr(Expr::new(kind, span))
+    }
+
+    fn stmt(&mut self, kind: StmtKind<'src>, span: Span) -> StmtId {
+        self.ast.add_stmt(Stmt::new(kind, span))
+    }
+
+    /// Binary or logical expression spanning both operands.
+    fn binary(
+        &mut self,
+        left: ExprId,
+        operator: Token<'src>,
+        right: ExprId,
+        kind: fn(ExprId, Token<'src>, ExprId) -> ExprKind<'src>,
+    ) -> ExprId {
+        let span = self.ast[left].span.to(self.ast[right].span);
+        self.expr(kind(left, operator, right), span)
+    }
+
     // GRAMMAR DEF
 
-    fn expression(&mut self) -> Result<Expr, ParseError> {
-        self.equality()
+    /// Errors are recorded and the parser moves on to the next statement,
+    /// `None` is returned in that case.
+    fn declaration(&mut self) -> Option<StmtId> {
+        match self.declaration_or_error() {
+            Ok(statement) => Some(statement),
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    fn declaration_or_error(&mut self) -> Result<StmtId, ParseError<'src>> {
+        if self.match_any_of(&[TokenKind::Class]) {
+            self.class_declaration()
+        } else if self.match_any_of(&[TokenKind::Fun]) {
+            let start = self.previous().span;
+            let function = self.function("function")?;
+            let span = self.span_from(start);
+            Ok(self.stmt(StmtKind::Function(function), span))
+        } else if self.match_any_of(&[TokenKind::Var]) {
+            self.var_declaration()
+        } else {
+            self.statement()
+        }
     }
 
-    fn equality(&mut self) -> Result<Expr, ParseError> {
+    fn class_declaration(&mut self) -> Result<StmtId, ParseError<'src>> {
+        let start = self.previous().span;
+
+        let (name, superclass) = self.in_context("in class declaration", |parser| {
+            let name = parser.consume(IDENTIFIER, "Expect class name.")?;
+
+            let superclass = if parser.match_any_of(&[TokenKind::Less]) {
+                let superclass = parser.consume(IDENTIFIER, "Expect superclass name.")?;
+                let span = superclass.span;
+                Some(parser.expr(ExprKind::Variable(superclass), span))
+            } else {
+                None
+            };
+
+            parser.consume(TokenKind::LeftBrace, "Expect '{' before class body.")?;
+            Ok((name, superclass))
+        })?;
+
+        let mut methods = Vec::new();
+        while self.peek().kind != TokenKind::RightBrace && !self.is_at_end() {
+            methods.push(self.function("method")?);
+        }
+
+        self.consume(TokenKind::RightBrace, "Expect '}' after class body.")?;
+        let span = self.span_from(start);
+        Ok(self.stmt(StmtKind::Class(name, superclass, methods), span))
+    }
+
+    /// `kind` is either "function" or "method", it is used in the error
+    /// messages.
+    fn function(&mut self, kind: &str) -> Result<FunctionId, ParseError<'src>> {
+        let name = self.consume(IDENTIFIER, &format!("Expect {} name.", kind))?;
+        self.consume(
+            TokenKind::LeftParen,
+            &format!("Expect '(' after {} name.", kind),
+        )?;
+
+        let params = self.in_context("in function parameters", |parser| {
+            let mut params = Vec::new();
+            if parser.peek().kind != TokenKind::RightParen {
+                loop {
+                    if params.len() == MAX_ARGUMENTS {
+                        let error = parser.error("Can't have more than 255 parameters.", &[]);
+                        parser.errors.push(error);
+                    }
+
+                    params.push(parser.consume(IDENTIFIER, "Expect parameter name.")?);
+
+                    if !parser.match_any_of(&[TokenKind::Comma]) {
+                        break;
+                    }
+                }
+            }
+            parser.consume(TokenKind::RightParen, "Expect ')' after parameters.")?;
+            Ok(params)
+        })?;
+
+        self.consume(
+            TokenKind::LeftBrace,
+            &format!("Expect '{{' before {} body.", kind),
+        )?;
+        let body = self.block()?;
+
+        let span = self.span_from(name.span);
+        Ok(self.ast.add_function(FunctionDecl {
+            name,
+            params,
+            body,
+            span,
+        }))
+    }
+
+    fn var_declaration(&mut self) -> Result<StmtId, ParseError<'src>> {
+        let start = self.previous().span;
+
+        let (name, initializer) = self.in_context("in variable declaration", |parser| {
+            let name = parser.consume(IDENTIFIER, "Expect variable name.")?;
+
+            let initializer = if parser.match_any_of(&[TokenKind::Equal]) {
+                Some(parser.expression()?)
+            } else {
+                None
+            };
+
+            parser.consume(
+                TokenKind::SemiColon,
+                "Expect ';' after variable declaration.",
+            )?;
+            Ok((name, initializer))
+        })?;
+
+        let span = self.span_from(start);
+        Ok(self.stmt(StmtKind::Var(name, initializer), span))
+    }
+
+    fn statement(&mut self) -> Result<StmtId, ParseError<'src>> {
+        if self.match_any_of(&[TokenKind::For]) {
+            self.for_statement()
+        } else if self.match_any_of(&[TokenKind::If]) {
+            self.if_statement()
+        } else if self.match_any_of(&[TokenKind::Print]) {
+            self.print_statement()
+        } else if self.match_any_of(&[TokenKind::Return]) {
+            self.return_statement()
+        } else if self.match_any_of(&[TokenKind::While]) {
+            self.while_statement()
+        } else if self.match_any_of(&[TokenKind::LeftBrace]) {
+            let start = self.previous().span;
+            let statements = self.block()?;
+            let span = self.span_from(start);
+            Ok(self.stmt(StmtKind::Block(statements), span))
+        } else {
+            self.expression_statement()
+        }
+    }
+
+    /// Parses the statements of a block. The opening brace has already been
+    /// consumed.
+    fn block(&mut self) -> Result<Vec<StmtId>, ParseError<'src>> {
+        let mut statements = Vec::new();
+
+        while self.peek().kind != TokenKind::RightBrace && !self.is_at_end() {
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
+        }
+
+        self.consume(TokenKind::RightBrace, "Expect '}' after block.")?;
+        Ok(statements)
+    }
+
+    /// There is no `for` node in the AST. The loop is desugared into a
+    /// `while` loop wrapped in a block holding the initializer:
+    ///
+    ///    { initializer; while (condition) { body; increment; } }
+    ///
+    /// The nodes created along the way span the whole `for` statement.
+    fn for_statement(&mut self) -> Result<StmtId, ParseError<'src>> {
+        let start = self.previous().span;
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'for'.")?;
+
+        let (initializer, condition, increment) =
+            self.in_context("in for loop clauses", |parser| {
+                let initializer = if parser.match_any_of(&[TokenKind::SemiColon]) {
+                    None
+                } else if parser.match_any_of(&[TokenKind::Var]) {
+                    Some(parser.var_declaration()?)
+                } else {
+                    Some(parser.expression_statement()?)
+                };
+
+                let condition = if parser.peek().kind != TokenKind::SemiColon {
+                    parser.expression()?
+                } else {
+                    // An omitted condition loops forever.
+                    let semicolon = parser.peek();
+                    let span = Span::new(semicolon.span.start, semicolon.span.start);
+                    let token = Token::new(TokenKind::True, "true", semicolon.line);
+                    parser.expr(ExprKind::Literal(token), span)
+                };
+                parser.consume(TokenKind::SemiColon, "Expect ';' after loop condition.")?;
+
+                let increment = if parser.peek().kind != TokenKind::RightParen {
+                    Some(parser.expression()?)
+                } else {
+                    None
+                };
+                parser.consume(TokenKind::RightParen, "Expect ')' after for clauses.")?;
+
+                Ok((initializer, condition, increment))
+            })?;
+
+        let mut body = self.statement()?;
+        let span = self.span_from(start);
+
+        if let Some(increment) = increment {
+            let increment_span = self.ast[increment].span;
+            let increment = self.stmt(StmtKind::Expression(increment), increment_span);
+            body = self.stmt(StmtKind::Block(vec![body, increment]), span);
+        }
+
+        body = self.stmt(StmtKind::While(condition, body), span);
+
+        if let Some(initializer) = initializer {
+            body = self.stmt(StmtKind::Block(vec![initializer, body]), span);
+        }
+
+        Ok(body)
+    }
+
+    fn if_statement(&mut self) -> Result<StmtId, ParseError<'src>> {
+        let start = self.previous().span;
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.in_context("in if condition", |parser| {
+            let condition = parser.expression()?;
+            parser.consume(TokenKind::RightParen, "Expect ')' after if condition.")?;
+            Ok(condition)
+        })?;
+
+        let then_branch = self.statement()?;
+        // The `else` binds to the nearest `if`.
+        let else_branch = if self.match_any_of(&[TokenKind::Else]) {
+            Some(self.statement()?)
+        } else {
+            None
+        };
+
+        let span = self.span_from(start);
+        Ok(self.stmt(StmtKind::If(condition, then_branch, else_branch), span))
+    }
+
+    fn while_statement(&mut self) -> Result<StmtId, ParseError<'src>> {
+        let start = self.previous().span;
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.in_context("in while condition", |parser| {
+            let condition = parser.expression()?;
+            parser.consume(TokenKind::RightParen, "Expect ')' after condition.")?;
+            Ok(condition)
+        })?;
+        let body = self.statement()?;
+
+        let span = self.span_from(start);
+        Ok(self.stmt(StmtKind::While(condition, body), span))
+    }
+
+    fn print_statement(&mut self) -> Result<StmtId, ParseError<'src>> {
+        let start = self.previous().span;
+        let value = self.expression()?;
+        self.consume(TokenKind::SemiColon, "Expect ';' after value.")?;
+        let span = self.span_from(start);
+        Ok(self.stmt(StmtKind::Print(value), span))
+    }
+
+    fn return_statement(&mut self) -> Result<StmtId, ParseError<'src>> {
+        let keyword = self.previous().clone();
+        let value = if self.peek().kind != TokenKind::SemiColon {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume(TokenKind::SemiColon, "Expect ';' after return value.")?;
+        let span = self.span_from(keyword.span);
+        Ok(self.stmt(StmtKind::Return(keyword, value), span))
+    }
+
+    fn expression_statement(&mut self) -> Result<StmtId, ParseError<'src>> {
+        let expr = self.expression()?;
+        self.consume(TokenKind::SemiColon, "Expect ';' after expression.")?;
+        let span = self.span_from(self.ast[expr].span);
+        Ok(self.stmt(StmtKind::Expression(expr), span))
+    }
+
+    fn expression(&mut self) -> Result<ExprId, ParseError<'src>> {
+        self.assignment()
+    }
+
+    /// The left-hand side is parsed as a regular expression and only then
+    /// checked to be a valid assignment target. This way we don't need an
+    /// arbitrary lookahead to find the '='. A valid target is turned into
+    /// the assignment in place, its node is reused.
+    fn assignment(&mut self) -> Result<ExprId, ParseError<'src>> {
+        let expr = self.or()?;
+
+        if self.match_any_of(&[TokenKind::Equal]) {
+            let equals = self.previous().clone();
+            let value = self.assignment()?;
+
+            let target = &self.ast[expr];
+            let kind = match &target.kind {
+                ExprKind::Variable(name) => ExprKind::Assign(name.clone(), value),
+                ExprKind::Get(object, name) => ExprKind::Set(*object, name.clone(), value),
+                _ => {
+                    // No need to synchronize, the parser isn't confused.
+                    let error = self.error_at(&equals, "Invalid assignment target.", &[]);
+                    self.errors.push(error);
+                    return Ok(expr);
+                }
+            };
+
+            let span = target.span.to(self.ast[value].span);
+            self.ast[expr] = Expr::new(kind, span);
+        }
+
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<ExprId, ParseError<'src>> {
+        let mut expr = self.and()?;
+
+        while self.match_any_of(&[TokenKind::Or]) {
+            let operator = self.previous().clone();
+            let right = self.and()?;
+            expr = self.binary(expr, operator, right, ExprKind::Logical);
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<ExprId, ParseError<'src>> {
+        let mut expr = self.equality()?;
+
+        while self.match_any_of(&[TokenKind::And]) {
+            let operator = self.previous().clone();
+            let right = self.equality()?;
+            expr = self.binary(expr, operator, right, ExprKind::Logical);
+        }
+
+        Ok(expr)
+    }
+
+    fn equality(&mut self) -> Result<ExprId, ParseError<'src>> {
         let mut expr = self.comparison()?;
 
         // handle the ( ...)* part of the rule for association
         while self.match_any_of(&[TokenKind::BangEqual, TokenKind::EqualEqual]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let right = self.comparison()?;
-            expr = Expr::Binary(Box::from(expr), operator, Box::from(right));
+            expr = self.binary(expr, operator, right, ExprKind::Binary);
         }
 
         Ok(expr)
     }
 
-    fn comparison(&mut self) -> Result<Expr, ParseError> {
+    fn comparison(&mut self) -> Result<ExprId, ParseError<'src>> {
         let mut expr = self.addition()?;
 
         // handle the ( ...)* part of the rule for association
@@ -113,75 +847,127 @@ impl Parser {
             TokenKind::Less,
             TokenKind::LessEqual,
         ]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let right = self.addition()?;
-            expr = Expr::Binary(Box::from(expr), operator, Box::from(right));
+            expr = self.binary(expr, operator, right, ExprKind::Binary);
         }
 
         Ok(expr)
     }
 
-    fn addition(&mut self) -> Result<Expr, ParseError> {
+    fn addition(&mut self) -> Result<ExprId, ParseError<'src>> {
         let mut expr = self.multiplication()?;
 
         // handle the ( ...)* part of the rule for association
         while self.match_any_of(&[TokenKind::Plus, TokenKind::Minus]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let right = self.multiplication()?;
-            expr = Expr::Binary(Box::from(expr), operator, Box::from(right));
+            expr = self.binary(expr, operator, right, ExprKind::Binary);
         }
 
         Ok(expr)
     }
 
-    fn multiplication(&mut self) -> Result<Expr, ParseError> {
+    fn multiplication(&mut self) -> Result<ExprId, ParseError<'src>> {
         let mut expr = self.unary()?;
 
         // handle the ( ...)* part of the rule for association
         while self.match_any_of(&[TokenKind::Star, TokenKind::Slash]) {
-            let operator = self.previous();
+            let operator = self.previous().clone();
             let right = self.unary()?;
-            expr = Expr::Binary(Box::from(expr), operator, Box::from(right));
+            expr = self.binary(expr, operator, right, ExprKind::Binary);
         }
 
         Ok(expr)
     }
 
-    fn unary(&mut self) -> Result<Expr, ParseError> {
+    fn unary(&mut self) -> Result<ExprId, ParseError<'src>> {
         match self.peek().kind {
             TokenKind::Bang | TokenKind::Minus => {
-                self.advance();
-                let operator = self.previous();
+                let operator = self.advance();
                 let right = self.unary()?;
-                Ok(Expr::Unary(operator, Box::from(right)))
+                let span = operator.span.to(self.ast[right].span);
+                Ok(self.expr(ExprKind::Unary(operator, right), span))
             }
-            _ => self.primary(),
+            _ => self.call(),
         }
     }
 
-    ///    primary        → NUMBER | STRING | "false" | "true" | "nil"
-    ///                   | "(" expression ")" ;
-    fn primary(&mut self) -> Result<Expr, ParseError> {
-        match self.peek().kind {
+    fn call(&mut self) -> Result<ExprId, ParseError<'src>> {
+        let mut expr = self.primary()?;
+
+        // Calls and property accesses can be chained: `a.b(1)(2).c`.
+        loop {
+            if self.match_any_of(&[TokenKind::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_any_of(&[TokenKind::Dot]) {
+                let name = self.consume(IDENTIFIER, "Expect property name after '.'.")?;
+                let span = self.ast[expr].span.to(name.span);
+                expr = self.expr(ExprKind::Get(expr, name), span);
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: ExprId) -> Result<ExprId, ParseError<'src>> {
+        let (arguments, paren) = self.in_context("in call arguments", |parser| {
+            let mut arguments = Vec::new();
+
+            if parser.peek().kind != TokenKind::RightParen {
+                loop {
+                    if arguments.len() == MAX_ARGUMENTS {
+                        let error = parser.error("Can't have more than 255 arguments.", &[]);
+                        parser.errors.push(error);
+                    }
+
+                    arguments.push(parser.expression()?);
+
+                    if !parser.match_any_of(&[TokenKind::Comma]) {
+                        break;
+                    }
+                }
+            }
+
+            let paren = parser.consume(TokenKind::RightParen, "Expect ')' after arguments.")?;
+            Ok((arguments, paren))
+        })?;
+
+        let span = self.ast[callee].span.to(paren.span);
+        Ok(self.expr(ExprKind::Call(callee, paren, arguments), span))
+    }
+
+    ///    primary        → NUMBER | STRING | "false" | "true" | "nil" | "this"
+    ///                   | "(" expression ")" | IDENTIFIER
+    ///                   | "super" "." IDENTIFIER ;
+    fn primary(&mut self) -> Result<ExprId, ParseError<'src>> {
+        let start = self.peek().span;
+        let kind = match self.peek().kind {
             TokenKind::False | TokenKind::True | TokenKind::Nil => {
-                self.advance();
-                Ok(Expr::Literal(self.previous()))
+                ExprKind::Literal(self.advance())
             }
-            TokenKind::Number(_) | TokenKind::String(_) => {
-                self.advance();
-                Ok(Expr::Literal(self.previous()))
+            TokenKind::Number(_) | TokenKind::String(_) => ExprKind::Literal(self.advance()),
+            TokenKind::Identifier(_) => ExprKind::Variable(self.advance()),
+            TokenKind::This => ExprKind::This(self.advance()),
+            TokenKind::Super => {
+                let keyword = self.advance();
+                self.consume(TokenKind::Dot, "Expect '.' after 'super'.")?;
+                let method = self.consume(IDENTIFIER, "Expect superclass method name.")?;
+                ExprKind::Super(keyword, method)
             }
             TokenKind::LeftParen => {
                 self.advance();
                 let expr = self.expression()?;
-                if self.consume(TokenKind::RightParen).is_none() {
-                    Err(ParseError::MissingParenthesis)
-                } else {
-                    Ok(Expr::Grouping(Box::from(expr)))
-                }
+                self.consume(TokenKind::RightParen, "Expect ')' after expression.")?;
+                ExprKind::Grouping(expr)
             }
-            _ => Err(ParseError::UnexpectedToken),
-        }
+            _ => return Err(self.error("Expect expression.", &[])),
+        };
+
+        let span = self.span_from(start);
+        Ok(self.expr(kind, span))
     }
 
     // Error Handling
@@ -214,112 +1000,608 @@ impl Parser {
     }
 }
 
-pub fn ast_dump(expr: &Expr) -> String {
-    let mut output = String::new();
+/// Prints an expression fully parenthesized, e.g. `((1)+((2)*(3)))`.
+pub fn ast_dump(ast: &Ast, expr: ExprId) -> String {
+    AstPrinter.visit_expr(ast, expr)
+}
 
-    match expr {
-        Expr::Literal(token) => {
-            output.push_str("(");
-            output.push_str(&token.lexeme);
-            output.push_str(")");
-        }
-        Expr::Unary(token, expr) => {
-            output.push_str("(");
-            output.push_str(&token.lexeme);
-            output.push_str(&ast_dump(expr.as_ref()));
-            output.push_str(")");
-        }
-        Expr::Binary(left, token, right) => {
-            output.push_str("(");
-            output.push_str(&ast_dump(left.as_ref()));
-            output.push_str(&token.lexeme);
-            output.push_str(&ast_dump(right.as_ref()));
-            output.push_str(")");
-        }
-        Expr::Grouping(expr) => {
-            output.push_str("(");
-            output.push_str(&ast_dump(expr.as_ref()));
-            output.push_str(")");
-        }
-    };
+struct AstPrinter;
+
+impl<'src> ExprVisitor<'src, String> for AstPrinter {
+    fn visit_literal(&mut self, _ast: &Ast<'src>, _expr: ExprId, value: &Token<'src>) -> String {
+        format!("({})", value.lexeme)
+    }
+
+    fn visit_unary(
+        &mut self,
+        ast: &Ast<'src>,
+        _expr: ExprId,
+        operator: &Token<'src>,
+        right: ExprId,
+    ) -> String {
+        format!("({}{})", operator.lexeme, self.visit_expr(ast, right))
+    }
 
-    output
+    fn visit_binary(
+        &mut self,
+        ast: &Ast<'src>,
+        _expr: ExprId,
+        left: ExprId,
+        operator: &Token<'src>,
+        right: ExprId,
+    ) -> String {
+        let left = self.visit_expr(ast, left);
+        format!(
+            "({}{}{})",
+            left,
+            operator.lexeme,
+            self.visit_expr(ast, right)
+        )
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast<'src>, _expr: ExprId, inner: ExprId) -> String {
+        format!("({})", self.visit_expr(ast, inner))
+    }
+
+    fn visit_variable(&mut self, _ast: &Ast<'src>, _expr: ExprId, name: &Token<'src>) -> String {
+        format!("({})", name.lexeme)
+    }
+
+    fn visit_assign(
+        &mut self,
+        ast: &Ast<'src>,
+        _expr: ExprId,
+        name: &Token<'src>,
+        value: ExprId,
+    ) -> String {
+        format!("({}={})", name.lexeme, self.visit_expr(ast, value))
+    }
+
+    fn visit_logical(
+        &mut self,
+        ast: &Ast<'src>,
+        expr: ExprId,
+        left: ExprId,
+        operator: &Token<'src>,
+        right: ExprId,
+    ) -> String {
+        self.visit_binary(ast, expr, left, operator, right)
+    }
+
+    fn visit_call(
+        &mut self,
+        ast: &Ast<'src>,
+        _expr: ExprId,
+        callee: ExprId,
+        _paren: &Token<'src>,
+        arguments: &[ExprId],
+    ) -> String {
+        let callee = self.visit_expr(ast, callee);
+        let arguments: Vec<String> = arguments
+            .iter()
+            .map(|&argument| self.visit_expr(ast, argument))
+            .collect();
+        format!("({}({}))", callee, arguments.join(","))
+    }
+
+    fn visit_get(
+        &mut self,
+        ast: &Ast<'src>,
+        _expr: ExprId,
+        object: ExprId,
+        name: &Token<'src>,
+    ) -> String {
+        format!("({}.{})", self.visit_expr(ast, object), name.lexeme)
+    }
+
+    fn visit_set(
+        &mut self,
+        ast: &Ast<'src>,
+        _expr: ExprId,
+        object: ExprId,
+        name: &Token<'src>,
+        value: ExprId,
+    ) -> String {
+        let object = self.visit_expr(ast, object);
+        format!(
+            "({}.{}={})",
+            object,
+            name.lexeme,
+            self.visit_expr(ast, value)
+        )
+    }
+
+    fn visit_this(&mut self, _ast: &Ast<'src>, _expr: ExprId, keyword: &Token<'src>) -> String {
+        format!("({})", keyword.lexeme)
+    }
+
+    fn visit_super(
+        &mut self,
+        _ast: &Ast<'src>,
+        _expr: ExprId,
+        keyword: &Token<'src>,
+        method: &Token<'src>,
+    ) -> String {
+        format!("({}.{})", keyword.lexeme, method.lexeme)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::scanner::Scanner;
+    use std::cell::Cell;
+
+    /// Messages of the errors found while parsing `source`.
+    fn parse_errors(source: &str) -> Vec<String> {
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens();
+        let (_, errors) = Parser::new(scanner.tokens).parse_partial();
+        errors.into_iter().map(|error| error.message).collect()
+    }
+
+    fn parse(source: &str) -> Ast<'_> {
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens();
+        Parser::new(scanner.tokens).parse().unwrap()
+    }
+
+    /// Top-level statements without their spans, to keep the patterns
+    /// short.
+    fn kinds<'a, 'src>(ast: &'a Ast<'src>) -> Vec<&'a StmtKind<'src>> {
+        ast.statements
+            .iter()
+            .map(|&statement| &ast[statement].kind)
+            .collect()
+    }
 
     #[test]
     fn print_literal() {
-        let number_literal = Token::new(TokenKind::Number(42.0), "42".to_owned(), 1);
-        let result = ast_dump(&Expr::Literal(number_literal));
-        assert_eq!("(42)", &result);
+        let mut ast = Ast::new();
+        let number_literal = Token::new(TokenKind::Number(42.0), "42", 1);
+        let expr = ast.add_expr(Expr::new(
+            ExprKind::Literal(number_literal),
+            Span::default(),
+        ));
+        assert_eq!("(42)", &ast_dump(&ast, expr));
     }
 
     #[test]
     fn print_unary() {
-        let minus_token = Token::new(TokenKind::Minus, "-".to_owned(), 1);
-        let literal_token = Token::new(TokenKind::Number(42.0), "42".to_owned(), 1);
-        let expr = Expr::Unary(minus_token, Box::from(Expr::Literal(literal_token)));
+        let mut ast = Ast::new();
+        let minus_token = Token::new(TokenKind::Minus, "-", 1);
+        let literal_token = Token::new(TokenKind::Number(42.0), "42", 1);
+        let literal = ast.add_expr(Expr::new(ExprKind::Literal(literal_token), Span::default()));
+        let expr = ast.add_expr(Expr::new(
+            ExprKind::Unary(minus_token, literal),
+            Span::default(),
+        ));
 
-        let result = ast_dump(&expr);
+        let result = ast_dump(&ast, expr);
         assert_eq!("(-(42))", &result);
     }
 
     #[test]
     fn test_parse() {
-        let mut scanner = Scanner::new("3 + 4".to_owned());
+        let mut scanner = Scanner::new("3 + 4;");
         scanner.scan_tokens();
+        let tokens = scanner.tokens.clone();
         let mut parser = Parser::new(scanner.tokens);
 
-        let expected = Expr::Binary(
-            Box::new(Expr::Literal(Token::new(
-                TokenKind::Number(3.0),
-                "3".to_owned(),
-                1,
-            ))),
-            Token::new(TokenKind::Plus, "+".to_owned(), 1),
-            Box::new(Expr::Literal(Token::new(
-                TokenKind::Number(4.0),
-                "4".to_owned(),
-                1,
-            ))),
-        );
+        // Added in the order the parser adds them, for the ids to match.
+        let mut expected = Ast::new();
+        let left = expected.add_expr(Expr::new(
+            ExprKind::Literal(tokens[0].clone()),
+            Span::new(0, 1),
+        ));
+        let right = expected.add_expr(Expr::new(
+            ExprKind::Literal(tokens[2].clone()),
+            Span::new(4, 5),
+        ));
+        let sum = expected.add_expr(Expr::new(
+            ExprKind::Binary(left, tokens[1].clone(), right),
+            Span::new(0, 5),
+        ));
+        let statement = expected.add_stmt(Stmt::new(StmtKind::Expression(sum), Span::new(0, 6)));
+        expected.statements.push(statement);
 
         assert_eq!(expected, parser.parse().unwrap());
     }
 
+    #[test]
+    fn assignment_reuses_target() {
+        let ast = parse("a = 1;");
+
+        let assign = match ast[ast.statements[0]].kind {
+            StmtKind::Expression(expr) => expr,
+            _ => panic!("Expected an expression statement"),
+        };
+        match ast[assign].kind {
+            ExprKind::Assign(_, value) => assert!(assign.index() < value.index()),
+            _ => panic!("Expected an assignment"),
+        }
+        assert_eq!(2, ast.expr_count());
+    }
+
     #[test]
     fn invalid_unary_parse() {
-        let invalid_unary = String::from("-");
-        let mut scanner = Scanner::new(invalid_unary);
-        scanner.scan_tokens();
-        let mut parser = Parser::new(scanner.tokens);
-        assert_eq!(Err(ParseError::UnexpectedToken), parser.parse());
+        assert_eq!(vec!["Expect expression."], parse_errors("-"));
     }
 
     #[test]
     fn invalid_binary_parse() {
-        let invalid_binary = String::from("3 +");
-        let mut scanner = Scanner::new(invalid_binary);
+        assert_eq!(vec!["Expect expression."], parse_errors("3 +"));
+    }
+
+    #[test]
+    fn missing_closing_parenthesis() {
+        assert_eq!(vec!["Expect ')' after expression."], parse_errors("(42"));
+    }
+
+    #[test]
+    fn print_statement() {
+        let ast = parse("print 42; 1;");
+        let statements = kinds(&ast);
+
+        assert_eq!(2, statements.len());
+        assert!(
+            matches!(statements[0], StmtKind::Print(expr) if matches!(ast[*expr].kind, ExprKind::Literal(_)))
+        );
+        assert!(
+            matches!(statements[1], StmtKind::Expression(expr) if matches!(ast[*expr].kind, ExprKind::Literal(_)))
+        );
+    }
+
+    #[test]
+    fn missing_semicolon() {
+        assert_eq!(vec!["Expect ';' after value."], parse_errors("print 42"));
+    }
+
+    #[test]
+    fn var_declaration() {
+        let ast = parse("var a = 1; var b;");
+        let statements = kinds(&ast);
+
+        assert!(matches!(statements[0], StmtKind::Var(name, Some(_)) if name.lexeme == "a"));
+        assert!(matches!(statements[1], StmtKind::Var(name, None) if name.lexeme == "b"));
+    }
+
+    #[test]
+    fn assignment_is_right_associative() {
+        let ast = parse("a = b = 1;");
+
+        match kinds(&ast).as_slice() {
+            [StmtKind::Expression(expr)] => assert_eq!("(a=(b=(1)))", ast_dump(&ast, *expr)),
+            _ => panic!("Expected a single expression statement"),
+        }
+    }
+
+    #[test]
+    fn invalid_assignment_target() {
+        assert_eq!(
+            vec!["Invalid assignment target."],
+            parse_errors("1 + a = 2;")
+        );
+    }
+
+    #[test]
+    fn unclosed_block() {
+        assert_eq!(vec!["Expect '}' after block."], parse_errors("{ var a;"));
+    }
+
+    #[test]
+    fn logical_precedence() {
+        let ast = parse("a or b and c;");
+
+        match kinds(&ast).as_slice() {
+            [StmtKind::Expression(expr)] => assert_eq!("((a)or((b)and(c)))", ast_dump(&ast, *expr)),
+            _ => panic!("Expected a single expression statement"),
+        }
+    }
+
+    #[test]
+    fn dangling_else() {
+        let ast = parse("if (a) if (b) 1; else 2;");
+
+        match kinds(&ast).as_slice() {
+            [StmtKind::If(_, then_branch, None)] => {
+                assert!(matches!(
+                    ast[*then_branch].kind,
+                    StmtKind::If(_, _, Some(_))
+                ))
+            }
+            _ => panic!("Expected the else to bind to the inner if"),
+        }
+    }
+
+    #[test]
+    fn for_is_desugared() {
+        let ast = parse("for (var i = 0; i < 3; i = i + 1) print i;");
+
+        match kinds(&ast).as_slice() {
+            [StmtKind::Block(statements)] => {
+                assert!(matches!(ast[statements[0]].kind, StmtKind::Var(_, Some(_))));
+                match &ast[statements[1]].kind {
+                    StmtKind::While(_, body) => {
+                        assert!(matches!(ast[*body].kind, StmtKind::Block(_)))
+                    }
+                    _ => panic!("Expected a while loop"),
+                }
+            }
+            _ => panic!("Expected a block"),
+        }
+    }
+
+    #[test]
+    fn empty_for_clauses() {
+        let ast = parse("for (;;) print 1;");
+
+        match kinds(&ast).as_slice() {
+            [StmtKind::While(condition, body)] => {
+                assert!(
+                    matches!(&ast[*condition].kind, ExprKind::Literal(token) if token.kind == TokenKind::True)
+                );
+                assert!(matches!(ast[*body].kind, StmtKind::Print(_)));
+            }
+            _ => panic!("Expected a bare while loop"),
+        }
+    }
+
+    #[test]
+    fn function_declaration() {
+        let ast = parse("fun add(a, b) { return a + b; }");
+
+        match kinds(&ast).as_slice() {
+            [StmtKind::Function(declaration)] => {
+                let declaration = &ast[*declaration];
+                assert_eq!("add", declaration.name.lexeme);
+                assert_eq!(2, declaration.params.len());
+                assert!(matches!(
+                    ast[declaration.body[0]].kind,
+                    StmtKind::Return(_, Some(_))
+                ));
+            }
+            _ => panic!("Expected a function declaration"),
+        }
+    }
+
+    #[test]
+    fn chained_calls() {
+        let ast = parse("f(1, 2)();");
+
+        match kinds(&ast).as_slice() {
+            [StmtKind::Expression(expr)] => assert_eq!("(((f)((1),(2)))())", ast_dump(&ast, *expr)),
+            _ => panic!("Expected a single expression statement"),
+        }
+    }
+
+    #[test]
+    fn too_many_arguments() {
+        let arguments = vec!["1"; 256].join(", ");
+        assert_eq!(
+            vec!["Can't have more than 255 arguments."],
+            parse_errors(&format!("f({});", arguments))
+        );
+    }
+
+    #[test]
+    fn class_declaration() {
+        let ast = parse("class B < A { init(a) {} method() {} }");
+
+        match kinds(&ast).as_slice() {
+            [StmtKind::Class(name, Some(superclass), methods)] => {
+                assert_eq!("B", name.lexeme);
+                assert!(
+                    matches!(&ast[*superclass].kind, ExprKind::Variable(name) if name.lexeme == "A")
+                );
+                assert_eq!(2, methods.len());
+                assert_eq!("init", ast[methods[0]].name.lexeme);
+            }
+            _ => panic!("Expected a class declaration"),
+        }
+    }
+
+    #[test]
+    fn properties() {
+        let ast = parse("a.b.c = this.d(super.e);");
+
+        match kinds(&ast).as_slice() {
+            [StmtKind::Expression(expr)] => {
+                assert_eq!("(((a).b).c=(((this).d)((super.e))))", ast_dump(&ast, *expr))
+            }
+            _ => panic!("Expected a single expression statement"),
+        }
+    }
+
+    #[test]
+    fn super_without_method() {
+        assert_eq!(vec!["Expect '.' after 'super'."], parse_errors("super;"));
+    }
+
+    #[test]
+    fn bare_expression() {
+        let mut scanner = Scanner::new("1 + 2");
         scanner.scan_tokens();
         let mut parser = Parser::new(scanner.tokens);
-        assert_eq!(Err(ParseError::UnexpectedToken), parser.parse());
+        let (ast, expr) = parser.parse_expression().unwrap();
+        assert_eq!("((1)+(2))", ast_dump(&ast, expr));
+        assert!(ast.statements.is_empty());
+
+        let mut scanner = Scanner::new("1 + 2; 3");
+        scanner.scan_tokens();
+        let mut parser = Parser::new(scanner.tokens);
+        let errors = parser.parse_expression().unwrap_err();
+        assert_eq!("Expect end of expression.", errors[0].message);
+        assert_eq!(";", errors[0].token.lexeme);
     }
 
-        #[test]
-    fn missing_closing_parenthesis() {
-        let missing_parenthesis = String::from("(42");
-        let mut scanner = Scanner::new(missing_parenthesis);
+    #[test]
+    fn spans() {
+        let source = "var a = f(1) + b.c;\nif (a) { print -a; }";
+        let ast = parse(source);
+        let text = |span: Span| &source[span.start..span.end];
+        let statements: Vec<&Stmt> = ast.statements.iter().map(|&id| &ast[id]).collect();
+
+        assert_eq!("var a = f(1) + b.c;", text(statements[0].span));
+        assert_eq!("if (a) { print -a; }", text(statements[1].span));
+
+        match &statements[0].kind {
+            StmtKind::Var(_, Some(initializer)) => {
+                let initializer = &ast[*initializer];
+                assert_eq!("f(1) + b.c", text(initializer.span));
+                match &initializer.kind {
+                    ExprKind::Binary(left, _, right) => {
+                        assert_eq!("f(1)", text(ast[*left].span));
+                        assert_eq!("b.c", text(ast[*right].span));
+                    }
+                    _ => panic!("Expected a binary expression"),
+                }
+            }
+            _ => panic!("Expected a variable declaration"),
+        }
+    }
+
+    #[test]
+    fn function_spans() {
+        let source = "class A { m(x) { return (x); } }";
+        let ast = parse(source);
+
+        match kinds(&ast).as_slice() {
+            [StmtKind::Class(_, None, methods)] => {
+                let method = &ast[methods[0]];
+                assert_eq!(
+                    "m(x) { return (x); }",
+                    &source[method.span.start..method.span.end]
+                );
+
+                let body = ast[method.body[0]].span;
+                assert_eq!("return (x);", &source[body.start..body.end]);
+            }
+            _ => panic!("Expected a class declaration"),
+        }
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let source = "var = 1;\nprint 2\nvar b;\n{ print (3; }\nprint 4;";
+        let mut scanner = Scanner::new(source);
         scanner.scan_tokens();
+        let (ast, errors) = Parser::new(scanner.tokens).parse_partial();
+
+        let lines: Vec<usize> = errors.iter().map(|error| error.token.line).collect();
+        assert_eq!(vec![1, 3, 4], lines);
+        assert_eq!("Expect variable name.", errors[0].message);
+        assert_eq!("var", errors[1].token.lexeme);
+        assert_eq!("Expect ';' after value.", errors[1].message);
+        assert_eq!("Expect ')' after expression.", errors[2].message);
 
-        for token in &scanner.tokens {
-            println!("Token: {:?}", token);
+        // The block survives without its broken statement.
+        match kinds(&ast).as_slice() {
+            [StmtKind::Block(block), StmtKind::Print(_)] => assert!(block.is_empty()),
+            _ => panic!("Expected the block and the last print"),
         }
+    }
 
-        let mut parser = Parser::new(scanner.tokens);
-        assert_eq!(Err(ParseError::MissingParenthesis), parser.parse());
+    #[test]
+    fn error_messages() {
+        let mut scanner = Scanner::new("var a = (1;\nprint a");
+        scanner.scan_tokens();
+        let errors = Parser::new(scanner.tokens).parse().unwrap_err();
+
+        assert_eq!(
+            "[line 1] Error at ';': Expect ')' after expression.",
+            errors[0].to_string()
+        );
+        assert_eq!(
+            "[line 2] Error at end: Expect ';' after value.",
+            errors[1].to_string()
+        );
+    }
+
+    #[test]
+    fn invalid_assignment_does_not_synchronize() {
+        assert_eq!(
+            vec!["Invalid assignment target.", "Expect ';' after value."],
+            parse_errors("1 = 2; print 3")
+        );
+    }
+
+    #[test]
+    fn expected_tokens_and_context() {
+        let mut scanner = Scanner::new("fun f(a b) {}");
+        scanner.scan_tokens();
+        let errors = Parser::new(scanner.tokens).parse().unwrap_err();
+
+        assert_eq!("Expect ')' after parameters.", errors[0].message);
+        assert_eq!("b", errors[0].token.lexeme);
+        assert_eq!(vec![TokenKind::RightParen], errors[0].expected);
+        assert_eq!(Some("in function parameters"), errors[0].context);
+    }
+
+    #[test]
+    fn context_ends_with_the_header() {
+        let mut scanner = Scanner::new("while (true) print;");
+        scanner.scan_tokens();
+        let errors = Parser::new(scanner.tokens).parse().unwrap_err();
+
+        assert_eq!("Expect expression.", errors[0].message);
+        assert!(errors[0].expected.is_empty());
+        assert_eq!(None, errors[0].context);
+    }
+
+    #[test]
+    fn jlox_messages() {
+        assert_eq!(vec!["Expect class name."], parse_errors("class {}"));
+        assert_eq!(
+            vec!["Expect '{' before method body."],
+            parse_errors("class A { m() }")
+        );
+        assert_eq!(
+            vec!["Expect ';' after variable declaration."],
+            parse_errors("var a = 1")
+        );
+        assert_eq!(
+            vec!["Expect ')' after for clauses."],
+            parse_errors("for (;; i = i + 1 print i;")
+        );
+        assert_eq!(
+            vec!["Expect property name after '.'."],
+            parse_errors("a.1;")
+        );
+    }
+
+    #[test]
+    fn pulls_tokens_lazily() {
+        let pulled = Cell::new(0);
+        let tokens = Scanner::new("1 + 2 3 4 5")
+            .map(Result::unwrap)
+            .inspect(|_| pulled.set(pulled.get() + 1));
+        let errors = Parser::new(tokens).parse_expression().unwrap_err();
+
+        assert_eq!("Expect end of expression.", errors[0].message);
+        assert_eq!(4, pulled.get());
+    }
+
+    #[test]
+    fn missing_eof() {
+        let mut scanner = Scanner::new("print 1;");
+        scanner.scan_tokens();
+        scanner.tokens.pop();
+
+        let ast = Parser::new(scanner.tokens).parse().unwrap();
+        assert_eq!(1, ast.statements.len());
+    }
+
+    #[test]
+    fn assignment_reuses_its_target() {
+        let ast = parse("a = 1 + 2;");
+
+        // `a` became the assignment, no node is left unused.
+        assert_eq!(4, ast.expr_count());
+        assert_eq!(1, ast.stmt_count());
+        match kinds(&ast).as_slice() {
+            [StmtKind::Expression(expr)] => assert_eq!("(a=((1)+(2)))", ast_dump(&ast, *expr)),
+            _ => panic!("Expected a single expression statement"),
+        }
     }
 }
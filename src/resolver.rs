@@ -0,0 +1,464 @@
+use crate::parser::{Ast, ExprId, ExprKind, FunctionId, StmtId, StmtKind};
+use crate::scanner::Token;
+use crate::symbol::Symbol;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Static error found by the resolver, before anything is executed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolveError<'src> {
+    pub token: Token<'src>,
+    pub message: String,
+}
+
+impl<'src> ResolveError<'src> {
+    fn new(token: &Token<'src>, message: &str) -> Self {
+        ResolveError {
+            token: token.clone(),
+            message: message.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for ResolveError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[line {}] Error at '{}': {}",
+            self.token.line, self.token.lexeme, self.message
+        )
+    }
+}
+
+impl std::error::Error for ResolveError<'_> {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+/// An `Ast` that went through the resolver, along with what the resolver
+/// found. This is what the interpreter runs.
+#[derive(Debug)]
+pub struct ResolvedAst<'src> {
+    pub ast: Ast<'src>,
+    /// Indexed by `ExprId`, see `depth`.
+    depths: Vec<Option<usize>>,
+}
+
+impl<'src> ResolvedAst<'src> {
+    /// Number of scopes between a variable use (or `this`, `super`) and the
+    /// scope declaring it. `None` means the variable is global.
+    pub fn depth(&self, expr: ExprId) -> Option<usize> {
+        self.depths[expr.index()]
+    }
+}
+
+/// Walks the AST once, before execution, to bind every variable use to the
+/// scope declaring it. The number of scopes between the use and the
+/// declaration is recorded for each `ExprKind::Variable` and
+/// `ExprKind::Assign`, see `ResolvedAst::depth`. Variables that are not
+/// found in any local scope are left unresolved and looked up in the globals
+/// at runtime.
+#[derive(Debug)]
+pub struct Resolver<'src> {
+    /// Local scopes only. The value tells whether the variable is fully
+    /// defined, it is `false` while its initializer is being resolved.
+    scopes: Vec<HashMap<Symbol, bool>>,
+    depths: Vec<Option<usize>>,
+    current_function: FunctionType,
+    current_class: ClassType,
+    errors: Vec<ResolveError<'src>>,
+}
+
+impl Default for Resolver<'_> {
+    fn default() -> Self {
+        Resolver::new()
+    }
+}
+
+impl<'src> Resolver<'src> {
+    pub fn new() -> Self {
+        Resolver {
+            scopes: Vec::new(),
+            depths: Vec::new(),
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Resolves the whole program and returns every error found along the
+    /// way.
+    pub fn resolve(mut self, ast: Ast<'src>) -> Result<ResolvedAst<'src>, Vec<ResolveError<'src>>> {
+        self.depths = vec![None; ast.expr_count()];
+        self.resolve_statements(&ast, &ast.statements);
+
+        if self.errors.is_empty() {
+            Ok(ResolvedAst {
+                ast,
+                depths: self.depths,
+            })
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn resolve_statements(&mut self, ast: &Ast<'src>, statements: &[StmtId]) {
+        for &statement in statements {
+            self.resolve_statement(ast, statement);
+        }
+    }
+
+    fn resolve_statement(&mut self, ast: &Ast<'src>, statement: StmtId) {
+        match &ast[statement].kind {
+            StmtKind::Expression(expr) | StmtKind::Print(expr) => {
+                self.resolve_expression(ast, *expr)
+            }
+            StmtKind::Var(name, initializer) => {
+                // Declared but not defined yet so that the initializer can't
+                // read the variable being declared.
+                self.declare(name);
+                if let Some(initializer) = initializer {
+                    self.resolve_expression(ast, *initializer);
+                }
+                self.define(name);
+            }
+            StmtKind::Block(statements) => {
+                self.begin_scope();
+                self.resolve_statements(ast, statements);
+                self.end_scope();
+            }
+            StmtKind::If(condition, then_branch, else_branch) => {
+                self.resolve_expression(ast, *condition);
+                self.resolve_statement(ast, *then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_statement(ast, *else_branch);
+                }
+            }
+            StmtKind::While(condition, body) => {
+                self.resolve_expression(ast, *condition);
+                self.resolve_statement(ast, *body);
+            }
+            StmtKind::Function(declaration) => {
+                // Defined eagerly so that the function can refer to itself.
+                let name = &ast[*declaration].name;
+                self.declare(name);
+                self.define(name);
+                self.resolve_function(ast, *declaration, FunctionType::Function);
+            }
+            StmtKind::Return(keyword, value) => {
+                if self.current_function == FunctionType::None {
+                    self.error(keyword, "Can't return from top-level code.");
+                }
+
+                if let Some(value) = value {
+                    if self.current_function == FunctionType::Initializer {
+                        self.error(keyword, "Can't return a value from an initializer.");
+                    }
+
+                    self.resolve_expression(ast, *value);
+                }
+            }
+            StmtKind::Class(name, superclass, methods) => {
+                let enclosing_class = self.current_class;
+                self.current_class = ClassType::Class;
+
+                self.declare(name);
+                self.define(name);
+
+                if let Some(superclass) = *superclass {
+                    if let ExprKind::Variable(superclass_name) = &ast[superclass].kind {
+                        if superclass_name.symbol() == name.symbol() {
+                            self.error(superclass_name, "A class can't inherit from itself.");
+                        }
+                    }
+
+                    self.current_class = ClassType::Subclass;
+                    self.resolve_expression(ast, superclass);
+
+                    self.begin_scope();
+                    self.define_name(Symbol::SUPER);
+                }
+
+                // Methods are resolved in a scope defining `this`, like the
+                // environment bound methods get at runtime.
+                self.begin_scope();
+                self.define_name(Symbol::THIS);
+
+                for &method in methods {
+                    let kind = if ast[method].name.symbol() == Symbol::INIT {
+                        FunctionType::Initializer
+                    } else {
+                        FunctionType::Method
+                    };
+                    self.resolve_function(ast, method, kind);
+                }
+
+                self.end_scope();
+
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+
+                self.current_class = enclosing_class;
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, ast: &Ast<'src>, declaration: FunctionId, kind: FunctionType) {
+        let declaration = &ast[declaration];
+        let enclosing_function = self.current_function;
+        self.current_function = kind;
+
+        self.begin_scope();
+        for param in &declaration.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_statements(ast, &declaration.body);
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+    }
+
+    fn resolve_expression(&mut self, ast: &Ast<'src>, expr: ExprId) {
+        match &ast[expr].kind {
+            ExprKind::Literal(_) => {}
+            ExprKind::Grouping(inner) | ExprKind::Unary(_, inner) => {
+                self.resolve_expression(ast, *inner)
+            }
+            ExprKind::Binary(left, _, right) | ExprKind::Logical(left, _, right) => {
+                self.resolve_expression(ast, *left);
+                self.resolve_expression(ast, *right);
+            }
+            ExprKind::Variable(name) => {
+                let in_own_initializer = self
+                    .scopes
+                    .last()
+                    .and_then(|scope| scope.get(&name.symbol()))
+                    == Some(&false);
+
+                if in_own_initializer {
+                    self.error(name, "Can't read local variable in its own initializer.");
+                }
+
+                self.resolve_local(expr, name);
+            }
+            ExprKind::Assign(name, value) => {
+                self.resolve_expression(ast, *value);
+                self.resolve_local(expr, name);
+            }
+            ExprKind::Call(callee, _, arguments) => {
+                self.resolve_expression(ast, *callee);
+                for &argument in arguments {
+                    self.resolve_expression(ast, argument);
+                }
+            }
+            // Properties are looked up dynamically, only the object is
+            // resolved.
+            ExprKind::Get(object, _) => self.resolve_expression(ast, *object),
+            ExprKind::Set(object, _, value) => {
+                self.resolve_expression(ast, *value);
+                self.resolve_expression(ast, *object);
+            }
+            ExprKind::This(keyword) => {
+                if self.current_class == ClassType::None {
+                    self.error(keyword, "Can't use 'this' outside of a class.");
+                    return;
+                }
+
+                self.resolve_local(expr, keyword);
+            }
+            ExprKind::Super(keyword, _) => match self.current_class {
+                ClassType::None => self.error(keyword, "Can't use 'super' outside of a class."),
+                ClassType::Class => {
+                    self.error(keyword, "Can't use 'super' in a class with no superclass.")
+                }
+                ClassType::Subclass => self.resolve_local(expr, keyword),
+            },
+        }
+    }
+
+    /// Looks for the innermost scope declaring `name`, used by `expr`.
+    /// Globals are left unresolved.
+    fn resolve_local(&mut self, expr: ExprId, name: &Token<'src>) {
+        self.depths[expr.index()] = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&name.symbol()));
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token<'src>) {
+        let already_declared = match self.scopes.last_mut() {
+            Some(scope) => scope.insert(name.symbol(), false).is_some(),
+            None => return,
+        };
+
+        if already_declared {
+            self.error(name, "Already a variable with this name in this scope.");
+        }
+    }
+
+    fn define(&mut self, name: &Token<'src>) {
+        self.define_name(name.symbol());
+    }
+
+    /// Defines an implicit variable like `this` or `super`.
+    fn define_name(&mut self, name: Symbol) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, true);
+        }
+    }
+
+    fn error(&mut self, token: &Token<'src>, message: &str) {
+        self.errors.push(ResolveError::new(token, message));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn parse(source: &str) -> Ast<'_> {
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens();
+        Parser::new(scanner.tokens).parse().unwrap()
+    }
+
+    fn resolve_errors(source: &str) -> Vec<String> {
+        match Resolver::new().resolve(parse(source)) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|e| e.message).collect(),
+        }
+    }
+
+    #[test]
+    fn globals_are_not_resolved() {
+        let resolved = Resolver::new().resolve(parse("var a = 1; a;")).unwrap();
+        let ast = &resolved.ast;
+
+        match ast[ast.statements[1]].kind {
+            StmtKind::Expression(expr) => assert_eq!(None, resolved.depth(expr)),
+            _ => panic!("Expected an expression statement"),
+        }
+    }
+
+    #[test]
+    fn locals_depth() {
+        let resolved = Resolver::new()
+            .resolve(parse("{ var a = 1; { a = 2; } }"))
+            .unwrap();
+        let ast = &resolved.ast;
+
+        let inner = match &ast[ast.statements[0]].kind {
+            StmtKind::Block(statements) => statements[1],
+            _ => panic!("Expected a block"),
+        };
+
+        match &ast[inner].kind {
+            StmtKind::Block(statements) => match ast[statements[0]].kind {
+                StmtKind::Expression(expr) => {
+                    assert!(matches!(ast[expr].kind, ExprKind::Assign(..)));
+                    assert_eq!(Some(1), resolved.depth(expr));
+                }
+                _ => panic!("Expected an expression statement"),
+            },
+            _ => panic!("Expected a block"),
+        }
+    }
+
+    #[test]
+    fn own_initializer() {
+        assert_eq!(
+            vec!["Can't read local variable in its own initializer."],
+            resolve_errors("var a = 1; { var a = a; }")
+        );
+        // Globals can refer to themselves, they are resolved at runtime.
+        assert!(resolve_errors("var a = a;").is_empty());
+    }
+
+    #[test]
+    fn duplicate_declaration() {
+        assert_eq!(
+            vec!["Already a variable with this name in this scope."],
+            resolve_errors("fun f(a) { var a; }")
+        );
+        assert!(resolve_errors("var a; var a;").is_empty());
+    }
+
+    #[test]
+    fn top_level_return() {
+        assert_eq!(
+            vec!["Can't return from top-level code."],
+            resolve_errors("return 1;")
+        );
+        assert!(resolve_errors("fun f() { return 1; }").is_empty());
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let errors = resolve_errors("return; { var a; var a; }");
+        assert_eq!(2, errors.len());
+    }
+
+    #[test]
+    fn this_outside_of_class() {
+        assert_eq!(
+            vec!["Can't use 'this' outside of a class."],
+            resolve_errors("fun f() { return this; }")
+        );
+        assert!(resolve_errors("class A { f() { return this; } }").is_empty());
+    }
+
+    #[test]
+    fn super_errors() {
+        assert_eq!(
+            vec!["Can't use 'super' outside of a class."],
+            resolve_errors("super.f();")
+        );
+        assert_eq!(
+            vec!["Can't use 'super' in a class with no superclass."],
+            resolve_errors("class A { f() { super.f(); } }")
+        );
+        assert!(resolve_errors("class A {} class B < A { f() { super.f(); } }").is_empty());
+    }
+
+    #[test]
+    fn inherit_from_itself() {
+        assert_eq!(
+            vec!["A class can't inherit from itself."],
+            resolve_errors("class A < A {}")
+        );
+    }
+
+    #[test]
+    fn return_from_initializer() {
+        assert_eq!(
+            vec!["Can't return a value from an initializer."],
+            resolve_errors("class A { init() { return 1; } }")
+        );
+        assert!(resolve_errors("class A { init() { return; } }").is_empty());
+    }
+}
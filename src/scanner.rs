@@ -1,5 +1,11 @@
-
-#[derive(Debug, Clone, PartialEq)]
+use crate::span::{SourceMap, Span};
+use crate::symbol::Symbol;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TokenKind {
     // Single-character tokens.
     LeftParen,
@@ -25,9 +31,10 @@ pub enum TokenKind {
     LessEqual,
 
     // Literals.
-    Identifier,
-    String(String),
-    Number(f32),
+    /// Identifiers and string literals are interned.
+    Identifier(Symbol),
+    String(Symbol),
+    Number(f64),
 
     // Keywords.
     And,
@@ -50,78 +57,276 @@ pub enum TokenKind {
     Eof,
 }
 
+/// How the kind of token is named in error messages.
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            TokenKind::LeftParen => "'('",
+            TokenKind::RightParen => "')'",
+            TokenKind::LeftBrace => "'{'",
+            TokenKind::RightBrace => "'}'",
+            TokenKind::Comma => "','",
+            TokenKind::Dot => "'.'",
+            TokenKind::Minus => "'-'",
+            TokenKind::Plus => "'+'",
+            TokenKind::SemiColon => "';'",
+            TokenKind::Slash => "'/'",
+            TokenKind::Star => "'*'",
+            TokenKind::Bang => "'!'",
+            TokenKind::BangEqual => "'!='",
+            TokenKind::Equal => "'='",
+            TokenKind::EqualEqual => "'=='",
+            TokenKind::Greater => "'>'",
+            TokenKind::GreaterEqual => "'>='",
+            TokenKind::Less => "'<'",
+            TokenKind::LessEqual => "'<='",
+            TokenKind::Identifier(_) => "identifier",
+            TokenKind::String(_) => "string",
+            TokenKind::Number(_) => "number",
+            TokenKind::And => "'and'",
+            TokenKind::Class => "'class'",
+            TokenKind::Else => "'else'",
+            TokenKind::False => "'false'",
+            TokenKind::Fun => "'fun'",
+            TokenKind::For => "'for'",
+            TokenKind::If => "'if'",
+            TokenKind::Nil => "'nil'",
+            TokenKind::Or => "'or'",
+            TokenKind::Print => "'print'",
+            TokenKind::Return => "'return'",
+            TokenKind::Super => "'super'",
+            TokenKind::This => "'this'",
+            TokenKind::True => "'true'",
+            TokenKind::Var => "'var'",
+            TokenKind::While => "'while'",
+            TokenKind::Eof => "end of input",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// A token borrows its text from the source. Scanning only allocates to
+/// intern identifiers and string literals seen for the first time.
 #[derive(Debug, PartialEq, Clone)]
-pub struct Token {
+pub struct Token<'src> {
     pub kind: TokenKind,
-    pub lexeme: String,
+    pub lexeme: &'src str,
+    /// Line and column (in chars, starting at 1) of the first char.
     pub line: usize,
+    pub column: usize,
+    pub span: Span,
 }
 
-impl Token {
-    pub fn new(kind: TokenKind, lexeme: String, line: usize) -> Self {
+impl<'src> Token<'src> {
+    /// Creates a token that doesn't come from the source, its span is empty.
+    pub fn new(kind: TokenKind, lexeme: &'src str, line: usize) -> Self {
         Token {
             kind,
             lexeme,
             line,
+            column: 0,
+            span: Span::default(),
+        }
+    }
+
+    /// Interned name of an identifier, `this` or `super` token.
+    pub fn symbol(&self) -> Symbol {
+        match self.kind {
+            TokenKind::Identifier(symbol) => symbol,
+            TokenKind::This => Symbol::THIS,
+            TokenKind::Super => Symbol::SUPER,
+            _ => Symbol::intern(self.lexeme),
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanErrorKind {
+    UnexpectedCharacter(char),
+    UnterminatedString,
+    /// Reported at the opening `/*` of the outermost comment.
+    UnterminatedBlockComment,
+    /// A backslash followed by a char that has no meaning in an escape.
+    UnknownEscape(char),
+    /// `\u` not followed by 1 to 6 hex digits between braces.
+    InvalidUnicodeEscape,
+    /// Well-formed `\u{...}` escape, but not a Unicode scalar value.
+    InvalidCodePoint(u32),
+    /// A number literal with missing digits, misplaced underscores or glued
+    /// to letters, like `0x`, `1_`, `1e` or `8a`.
+    MalformedNumber,
+}
+
+/// Lexical error. The scanner doesn't report anything itself, it is up to
+/// the caller to decide how to render the errors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanError {
+    pub kind: ScanErrorKind,
+    /// Line and column (in chars, starting at 1) where the faulty lexeme
+    /// starts.
+    pub line: usize,
+    pub column: usize,
+    /// Byte offsets of the faulty lexeme in the source.
+    pub span: Span,
+    pub message: String,
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[line {}] Error: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScanError {}
+
+/// Turns source code into tokens. The scanner is an iterator producing the
+/// tokens one at a time, as they are needed, followed by a final `Eof`
+/// token. Errors are produced in the middle of the tokens and scanning goes
+/// on after them.
 #[derive(Debug)]
-pub struct Scanner {
-    pub source: String,
-    pub tokens: Vec<Token>,
-    pub had_errors: bool,
-    // start of the current lexeme
+pub struct Scanner<'src> {
+    pub source: &'src str,
+    /// Only filled by `scan_tokens`.
+    pub tokens: Vec<Token<'src>>,
+    /// Scanning a single lexeme can produce several items: a string literal
+    /// with several invalid escapes for instance. They wait here until they
+    /// are asked for.
+    pending: VecDeque<Result<Token<'src>, ScanError>>,
+    /// Whether the `Eof` token has been produced.
+    done: bool,
+    // Byte offsets in `source`: start of the current lexeme and position of
+    // the next char. They always sit on char boundaries.
     start: usize,
     current: usize,
+    // Position of the next char.
     line: usize,
+    column: usize,
+    // Position of the start of the current lexeme.
+    start_line: usize,
+    start_column: usize,
+    /// Offset of `source` in the whole text it is part of. Spans are given
+    /// in the whole text.
+    offset: usize,
 }
 
-impl Scanner {
-    pub fn new(source: String) -> Self {
+impl<'src> Scanner<'src> {
+    pub fn new(source: &'src str) -> Self {
+        Scanner::starting_at(source, 0, 1)
+    }
+
+    /// Scanner for a piece of a larger text, like one input of the REPL in
+    /// its `SourceMap`: spans are shifted by `offset` and lines are counted
+    /// from `line`.
+    pub fn starting_at(source: &'src str, offset: usize, line: usize) -> Self {
         Scanner {
-            source: source,
+            source,
             tokens: Vec::new(),
-            had_errors: false,
+            pending: VecDeque::new(),
+            done: false,
             start: 0,
             current: 0,
-            line: 1,
+            line,
+            column: 1,
+            start_line: line,
+            start_column: 1,
+            offset,
+        }
+    }
+
+    /// Tells whether more input could complete `source`: a string literal or
+    /// a block comment is still open or there are more opening
+    /// parentheses/braces than closing ones. Nothing is reported, the REPL
+    /// uses it to ask for more lines.
+    pub fn is_incomplete(source: &str) -> bool {
+        let mut depth = 0;
+
+        for item in Scanner::new(source) {
+            match item {
+                Ok(token) => match token.kind {
+                    TokenKind::LeftParen | TokenKind::LeftBrace => depth += 1,
+                    TokenKind::RightParen | TokenKind::RightBrace => depth -= 1,
+                    _ => {}
+                },
+                Err(error) => match error.kind {
+                    ScanErrorKind::UnterminatedString | ScanErrorKind::UnterminatedBlockComment => {
+                        return true
+                    }
+                    _ => {}
+                },
+            }
         }
+
+        depth > 0
     }
 
-    pub fn scan_tokens(&mut self) {
-        while !self.is_at_end() {
-            self.start = self.current;
-            self.scan_token();
+    /// Scans the whole source at once: fills `tokens` and returns the errors
+    /// found along the way.
+    pub fn scan_tokens(&mut self) -> Vec<ScanError> {
+        let mut errors = Vec::new();
+
+        while let Some(item) = self.next() {
+            match item {
+                Ok(token) => self.tokens.push(token),
+                Err(error) => errors.push(error),
+            }
         }
 
-        self.tokens
-            .push(Token::new(TokenKind::Eof, "".to_owned(), self.line));
+        errors
+    }
+
+    /// Records an error on the current lexeme.
+    fn error(&mut self, kind: ScanErrorKind) {
+        self.error_from(kind, self.start, self.start_line, self.start_column);
     }
 
-    fn error(&mut self, line: usize, message: String) {
-        self.had_errors = true;
-        println!("Error at line {}: {}", line, message);
+    /// Records an error going from the given position to the current one.
+    fn error_from(&mut self, kind: ScanErrorKind, start: usize, line: usize, column: usize) {
+        let message = match &kind {
+            ScanErrorKind::UnexpectedCharacter(_) => "Unexpected character.".to_owned(),
+            ScanErrorKind::UnterminatedString => "Unterminated string.".to_owned(),
+            ScanErrorKind::UnterminatedBlockComment => "Unterminated block comment.".to_owned(),
+            ScanErrorKind::UnknownEscape(c) => format!("Unknown escape sequence '\\{}'.", c),
+            ScanErrorKind::InvalidUnicodeEscape => {
+                "Invalid unicode escape, expect '\\u{' followed by 1 to 6 hex digits and '}'."
+                    .to_owned()
+            }
+            ScanErrorKind::InvalidCodePoint(value) => {
+                format!("Invalid code point '{:X}' in unicode escape.", value)
+            }
+            ScanErrorKind::MalformedNumber => "Malformed number.".to_owned(),
+        };
+
+        self.pending.push_back(Err(ScanError {
+            kind,
+            line,
+            column,
+            span: self.span_from(start),
+            message,
+        }));
     }
 
     fn add_token(&mut self, kind: TokenKind) {
-        // Beware that we are slicing bytes here. Not actual characters.
         let text_slice = &self.source[self.start..self.current];
-        let token = Token::new(kind, text_slice.to_owned(), self.line);
+        let token = Token {
+            kind,
+            lexeme: text_slice,
+            line: self.start_line,
+            column: self.start_column,
+            span: self.span_from(self.start),
+        };
 
-        self.tokens.push(token);
+        self.pending.push_back(Ok(token));
     }
 
     fn scan_token(&mut self) {
         let c = match self.advance() {
             Some(c) => c,
-            None => return
+            None => return,
         };
 
         match c {
             // Single char tokens
-            '(' => self.add_token(TokenKind::LeftParen,),
+            '(' => self.add_token(TokenKind::LeftParen),
             ')' => self.add_token(TokenKind::RightParen),
             '{' => self.add_token(TokenKind::LeftBrace),
             '}' => self.add_token(TokenKind::RightBrace),
@@ -140,7 +345,7 @@ impl Scanner {
                     TokenKind::Bang
                 };
                 self.add_token(token)
-            },
+            }
             '=' => {
                 let token = if self.advance_if_matches('=') {
                     TokenKind::EqualEqual
@@ -148,7 +353,7 @@ impl Scanner {
                     TokenKind::Equal
                 };
                 self.add_token(token)
-            },
+            }
             '<' => {
                 let token = if self.advance_if_matches('=') {
                     TokenKind::LessEqual
@@ -156,7 +361,7 @@ impl Scanner {
                     TokenKind::Less
                 };
                 self.add_token(token)
-            },
+            }
             '>' => {
                 let token = if self.advance_if_matches('=') {
                     TokenKind::GreaterEqual
@@ -164,34 +369,37 @@ impl Scanner {
                     TokenKind::Greater
                 };
                 self.add_token(token)
-            },
+            }
 
-            // '/' can be a commented line.
+            // '/' can start a comment.
             '/' => {
                 if self.advance_if_matches('/') {
                     // consume the comment without doing anything with it.
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                } else if self.advance_if_matches('*') {
+                    self.block_comment();
                 } else {
                     self.add_token(TokenKind::Slash);
                 }
-            },
+            }
 
             // Eats whitespace
-            ' ' | '\r' | '\t' => { /* Do Nothing */},
+            ' ' | '\r' | '\t' => { /* Do Nothing */ }
 
-            '\n' => self.line = self.line + 1,
+            // `advance` keeps track of the lines.
+            '\n' => { /* Do Nothing */ }
 
             // literals
             '"' => self.string_literal(),
             '0'..='9' => self.number_literal(),
 
             // identifer & keywords
-            'a'..='z' | 'A'..='Z' | '_' => self.identifier(),
+            c if is_identifier_start(c) => self.identifier(),
 
             // Number literals & (reserved) words
-            _ => self.error(self.line, "Unexpected character".to_owned())
+            c => self.error(ScanErrorKind::UnexpectedCharacter(c)),
         }
     }
 
@@ -215,94 +423,465 @@ impl Scanner {
             "while" => TokenKind::While,
 
             // Not a reserved keyword
-            _ => TokenKind::Identifier
+            _ => TokenKind::Identifier(Symbol::intern(identifier)),
         }
     }
 
     fn identifier(&mut self) {
-        while self.peek().is_alphanumeric() {
+        while is_identifier_continue(self.peek()) {
             self.advance();
         }
 
-        let identifier_value = &self.source[self.start .. self.current];
+        let identifier_value = &self.source[self.start..self.current];
         self.add_token(self.token_for(identifier_value));
     }
 
+    /// Decimal numbers with an optional fraction and exponent (`1.5e-3`),
+    /// or integers in hexadecimal (`0xFF`) and binary (`0b1010`). Digits can
+    /// be separated by underscores (`1_000`).
     fn number_literal(&mut self) {
-        while self.peek().is_digit(10) {
+        let radix = match (&self.source[self.start..self.current], self.peek()) {
+            ("0", 'x') | ("0", 'X') => 16,
+            ("0", 'b') | ("0", 'B') => 2,
+            _ => 10,
+        };
+
+        let value = if radix == 10 {
+            self.decimal_number()
+        } else {
+            // Skip the prefix.
             self.advance();
+            let digits_start = self.current;
+            let valid = self.digits(digits_start, radix);
+
+            let value = self.source[digits_start..self.current]
+                .chars()
+                .filter_map(|c| c.to_digit(radix))
+                .fold(0.0, |value, digit| {
+                    value * f64::from(radix) + f64::from(digit)
+                });
+            Some(value).filter(|_| valid)
+        };
+
+        // Anything glued to the number makes it malformed, rather than
+        // silently starting an identifier.
+        let glued = is_identifier_continue(self.peek());
+        while is_identifier_continue(self.peek()) {
+            self.advance();
+        }
+
+        match value {
+            Some(value) if !glued => self.add_token(TokenKind::Number(value)),
+            _ => self.error(ScanErrorKind::MalformedNumber),
         }
+    }
+
+    /// The first digit is already consumed.
+    fn decimal_number(&mut self) -> Option<f64> {
+        let mut valid = self.digits(self.start, 10);
 
         // Fractional part
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             // consume '.'
             self.advance();
+            valid &= self.digits(self.current, 10);
+        }
 
-            while self.peek().is_digit(10) {
+        if self.peek() == 'e' || self.peek() == 'E' {
+            self.advance();
+            if self.peek() == '+' || self.peek() == '-' {
                 self.advance();
             }
+            valid &= self.digits(self.current, 10);
         }
 
-        let literal_value = &self.source[self.start .. self.current];
-        let double_value = literal_value.parse::<f32>().unwrap();
-        
-        self.add_token(TokenKind::Number(double_value));
+        let literal = &self.source[self.start..self.current];
+        let literal = if literal.contains('_') {
+            Cow::Owned(literal.replace('_', ""))
+        } else {
+            Cow::Borrowed(literal)
+        };
+
+        // Once well-formed, Rust accepts whatever we scanned.
+        literal.parse().ok().filter(|_| valid)
+    }
+
+    /// Consumes digits in the given radix and underscores. Tells whether the
+    /// digits going from `start` are well-formed: at least one digit and
+    /// underscores only between digits.
+    fn digits(&mut self, start: usize, radix: u32) -> bool {
+        while self.peek().is_digit(radix) || self.peek() == '_' {
+            self.advance();
+        }
+
+        let digits = &self.source[start..self.current];
+        !digits.is_empty() && !digits.starts_with('_') && !digits.ends_with('_')
+    }
+
+    /// Skips a `/* ... */` comment, the opening `/*` being consumed. Block
+    /// comments nest: `/* a /* b */ c */` is a single comment.
+    fn block_comment(&mut self) {
+        let mut depth = 1;
+
+        while depth > 0 {
+            match self.advance() {
+                None => {
+                    self.error(ScanErrorKind::UnterminatedBlockComment);
+                    return;
+                }
+                Some('/') if self.advance_if_matches('*') => depth += 1,
+                Some('*') if self.advance_if_matches('/') => depth -= 1,
+                Some(_) => {}
+            }
+        }
     }
 
+    /// The lexeme keeps the raw text, quotes and escapes included. The
+    /// token kind holds the decoded value.
     fn string_literal(&mut self) {
-        while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line = self.line + 1;
+        let source = self.source;
+        // Only allocated once an escape is found.
+        let mut decoded: Option<String> = None;
+
+        loop {
+            let position = self.current;
+            match self.advance() {
+                None => {
+                    self.error(ScanErrorKind::UnterminatedString);
+                    return;
+                }
+                Some('"') => break,
+                Some('\\') => {
+                    let value =
+                        decoded.get_or_insert_with(|| source[self.start + 1..position].to_owned());
+                    if let Some(c) = self.escape() {
+                        value.push(c);
+                    }
+                }
+                Some(c) => {
+                    if let Some(value) = &mut decoded {
+                        value.push(c);
+                    }
+                }
             }
+        }
+
+        let value = match decoded {
+            Some(value) => Cow::Owned(value),
+            // Without the quotes.
+            None => Cow::Borrowed(&source[self.start + 1..self.current - 1]),
+        };
+        self.add_token(TokenKind::String(Symbol::intern(&value)));
+    }
+
+    /// Decodes the escape sequence following a backslash. Invalid sequences
+    /// are reported and skipped, the string goes on.
+    fn escape(&mut self) -> Option<char> {
+        // The backslash is a single byte and a single column.
+        let (start, line, column) = (self.current - 1, self.line, self.column - 1);
+
+        let decoded = match self.advance()? {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            '0' => Ok('\0'),
+            'u' => self.unicode_escape(),
+            c => Err(ScanErrorKind::UnknownEscape(c)),
+        };
 
+        decoded
+            .map_err(|kind| self.error_from(kind, start, line, column))
+            .ok()
+    }
+
+    /// `\u{XXXX}`, the `\u` being already consumed. Nothing past the
+    /// faulty char is consumed so that a closing quote isn't missed.
+    fn unicode_escape(&mut self) -> Result<char, ScanErrorKind> {
+        if !self.advance_if_matches('{') {
+            return Err(ScanErrorKind::InvalidUnicodeEscape);
+        }
+
+        let digits_start = self.current;
+        while self.peek().is_ascii_hexdigit() {
             self.advance();
         }
+        let digits_end = self.current;
+        let count = digits_end - digits_start;
+        let closed = self.advance_if_matches('}');
 
-        if self.is_at_end() {
-            self.error(self.line, "Unterminated string".to_owned());
-            return
-        } 
-        
-        // closing quote
-        self.advance();
+        if count == 0 || count > 6 || !closed {
+            return Err(ScanErrorKind::InvalidUnicodeEscape);
+        }
 
-        // +1/-1 because we don't want the quote
-        let literal_value = &self.source[self.start +1 .. self.current -1];
-        self.add_token(TokenKind::String(literal_value.to_owned()));
+        // At most 6 hex digits, it always fits.
+        let value = u32::from_str_radix(&self.source[digits_start..digits_end], 16).unwrap();
+        char::from_u32(value).ok_or(ScanErrorKind::InvalidCodePoint(value))
     }
 
     /// Get the next char without consuming it.
     fn peek(&self) -> char {
-        return self.source.chars().nth(self.current).unwrap_or('\0');
+        self.remaining().next().unwrap_or('\0')
     }
 
     fn peek_next(&self) -> char {
-        return self.source.chars().nth(self.current + 1).unwrap_or('\0');
+        self.remaining().nth(1).unwrap_or('\0')
     }
 
     /// consumes the next char if it matches the expected one.
     fn advance_if_matches(&mut self, expected: char) -> bool {
-        match self.source.chars().nth(self.current) {
-            Some(c) => {
-                if c == expected {
-                    self.current = self.current + 1;
-                    return true;
-                } else {
-                    return false;
-                }
-            }
-            None => return false,
+        if !self.is_at_end() && self.peek() == expected {
+            self.advance();
+            true
+        } else {
+            false
         }
     }
 
     fn advance(&mut self) -> Option<char> {
-        self.current = self.current + 1;
-        self.source.chars().nth(self.current - 1)
+        let c = self.remaining().next()?;
+        self.current += c.len_utf8();
+
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        Some(c)
+    }
+
+    /// Chars that have not been consumed yet. Decoding starts at `current`,
+    /// so looking ahead is O(1) whatever the size of the source.
+    fn remaining(&self) -> std::str::Chars<'_> {
+        self.source[self.current..].chars()
     }
 
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
+
+    /// Span from `start` to the current position.
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(self.offset + start, self.offset + self.current)
+    }
+}
+
+impl<'src> Iterator for Scanner<'src> {
+    type Item = Result<Token<'src>, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() && !self.done {
+            if self.is_at_end() {
+                self.pending.push_back(Ok(Token {
+                    kind: TokenKind::Eof,
+                    lexeme: "",
+                    line: self.line,
+                    column: self.column,
+                    span: self.span_from(self.current),
+                }));
+                self.done = true;
+            } else {
+                self.start = self.current;
+                self.start_line = self.line;
+                self.start_column = self.column;
+                self.scan_token();
+            }
+        }
+
+        self.pending.pop_front()
+    }
+}
+
+/// Pieces read by a `StreamScanner` end at the first line end past this many
+/// bytes, or earlier when no more input is available yet.
+const PIECE_SIZE: usize = 8 * 1024;
+
+/// Longest piece a `StreamScanner` reads: a line, or a string or comment
+/// spanning lines, can't be longer than this.
+const MAX_PIECE_SIZE: usize = 1024 * 1024;
+
+/// Scans source read from `reader`, a piece at a time, so that parsing
+/// starts before the whole input has arrived from a pipe and only one piece
+/// is scanned at once. Pieces are whole lines, never ending inside a string
+/// or a comment, and are added to `source_map` for tokens to borrow from.
+///
+/// Reading stops at the first I/O error, see `take_error`.
+pub struct StreamScanner<'src, R> {
+    reader: BufReader<R>,
+    source_map: &'src SourceMap,
+    /// Scanner of the current piece.
+    scanner: Option<Scanner<'src>>,
+    /// Line the next piece starts at.
+    line: usize,
+    /// Lexeme open after the bytes read so far.
+    state: LexemeState,
+    /// `Eof` of the last piece, given once the input is exhausted.
+    eof: Option<Token<'src>>,
+    error: Option<io::Error>,
+}
+
+impl<'src, R: Read> StreamScanner<'src, R> {
+    pub fn new(reader: R, source_map: &'src SourceMap) -> Self {
+        StreamScanner {
+            reader: BufReader::new(reader),
+            source_map,
+            scanner: None,
+            line: 1,
+            state: LexemeState::Code,
+            eof: None,
+            error: None,
+        }
+    }
+
+    /// Error that stopped reading, the tokens end with what was read before.
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    /// Reads the next piece, `None` at the end of the input. Each byte is
+    /// looked at once, the open lexeme is carried over from one read to the
+    /// next.
+    fn read_piece(&mut self) -> Option<String> {
+        let mut bytes = Vec::new();
+
+        while self.error.is_none() {
+            let buffer = match self.reader.fill_buf() {
+                Ok([]) => break,
+                Ok(buffer) => buffer,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    self.error = Some(error);
+                    break;
+                }
+            };
+
+            let mut used = 0;
+            let mut pause = false;
+            for &byte in buffer {
+                used += 1;
+                self.state = self.state.next(byte);
+                if byte == b'\n' && self.state == LexemeState::Code {
+                    pause = bytes.len() + used >= PIECE_SIZE || used == buffer.len();
+                    if pause {
+                        break;
+                    }
+                }
+            }
+            bytes.extend_from_slice(&buffer[..used]);
+            self.reader.consume(used);
+
+            if pause {
+                break;
+            }
+            if bytes.len() > MAX_PIECE_SIZE {
+                self.error = Some(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Lines, and strings and comments spanning lines, \
+                         can't be longer than {MAX_PIECE_SIZE} bytes."
+                    ),
+                ));
+                return None;
+            }
+        }
+
+        if bytes.is_empty() {
+            return None;
+        }
+        match String::from_utf8(bytes) {
+            Ok(text) => Some(text),
+            Err(error) => {
+                self.error = Some(io::Error::new(io::ErrorKind::InvalidData, error));
+                None
+            }
+        }
+    }
+}
+
+impl<'src, R: Read> Iterator for StreamScanner<'src, R> {
+    type Item = Result<Token<'src>, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.scanner.as_mut().and_then(Iterator::next) {
+                Some(Ok(token)) if token.kind == TokenKind::Eof => self.eof = Some(token),
+                Some(item) => return Some(item),
+                None => {}
+            }
+
+            // Even empty input has a piece, for its `Eof` to have a place.
+            let text = match self.read_piece() {
+                Some(text) => text,
+                None if self.scanner.is_none() => String::new(),
+                None => return self.eof.take().map(Ok),
+            };
+            let line = self.line;
+            self.line += text.matches('\n').count();
+
+            let (start, text) = self.source_map.add(text, line);
+            self.scanner = Some(Scanner::starting_at(text, start, line));
+        }
+    }
+}
+
+/// Where a byte of source is, as far as strings and comments go. Much
+/// cheaper than scanning: only the rules for where those start and end are
+/// followed, a byte at a time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LexemeState {
+    Code,
+    /// After a `/` that may start a comment.
+    Slash,
+    String,
+    /// After a `\` in a string, whatever follows can't end it.
+    Escape,
+    LineComment,
+    /// Inside this many nested block comments.
+    BlockComment(usize),
+    /// After a `/` that may open a nested block comment.
+    BlockCommentSlash(usize),
+    /// After a `*` that may close a block comment.
+    BlockCommentStar(usize),
+}
+
+impl LexemeState {
+    fn next(self, byte: u8) -> LexemeState {
+        use LexemeState::*;
+
+        match (self, byte) {
+            (Code, b'"') => String,
+            (Code, b'/') => Slash,
+            (Code, _) => Code,
+            (Slash, b'/') => LineComment,
+            (Slash, b'*') => BlockComment(1),
+            (Slash, _) => Code.next(byte),
+            (String, b'"') => Code,
+            (String, b'\\') => Escape,
+            (String, _) | (Escape, _) => String,
+            (LineComment, b'\n') => Code,
+            (LineComment, _) => LineComment,
+            (BlockComment(depth), b'/') => BlockCommentSlash(depth),
+            (BlockComment(depth), b'*') => BlockCommentStar(depth),
+            (BlockComment(depth), _) => BlockComment(depth),
+            (BlockCommentSlash(depth), b'*') => BlockComment(depth + 1),
+            (BlockCommentSlash(depth), _) => BlockComment(depth).next(byte),
+            (BlockCommentStar(1), b'/') => Code,
+            (BlockCommentStar(depth), b'/') => BlockComment(depth - 1),
+            (BlockCommentStar(depth), _) => BlockComment(depth).next(byte),
+        }
+    }
+}
+
+/// Identifiers can use any Unicode letter, not only ASCII ones.
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_identifier_continue(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
 }
 
 #[cfg(test)]
@@ -311,25 +890,42 @@ mod tests {
 
     #[test]
     fn new() {
-        let scanner = Scanner::new("".to_owned());
-        assert!(!scanner.had_errors);
+        let scanner = Scanner::new("");
+        assert!(scanner.tokens.is_empty());
     }
 
     #[test]
     fn empty_source() {
-        let mut scanner = Scanner::new("".to_owned());
-        scanner.scan_tokens();
+        let mut scanner = Scanner::new("");
+        let errors = scanner.scan_tokens();
 
-        assert!(!scanner.had_errors);
+        assert!(errors.is_empty());
         assert_eq!(1, scanner.tokens.len());
         assert_eq!(TokenKind::Eof, scanner.tokens[0].kind);
     }
 
+    #[test]
+    fn iterator() {
+        let kinds: Vec<_> = Scanner::new("a @ 1")
+            .map(|item| item.map(|token| token.kind).map_err(|error| error.kind))
+            .collect();
+
+        assert_eq!(
+            vec![
+                Ok(TokenKind::Identifier(Symbol::intern("a"))),
+                Err(ScanErrorKind::UnexpectedCharacter('@')),
+                Ok(TokenKind::Number(1.0)),
+                Ok(TokenKind::Eof),
+            ],
+            kinds
+        );
+    }
+
     #[test]
     fn single_char_tokens() {
-        let mut scanner = Scanner::new("{}()+".to_owned());
-        scanner.scan_tokens();
-        assert!(!scanner.had_errors);
+        let mut scanner = Scanner::new("{}()+");
+        let errors = scanner.scan_tokens();
+        assert!(errors.is_empty());
         assert_eq!(6, scanner.tokens.len())
     }
 
@@ -340,23 +936,22 @@ mod tests {
         <=
         +"#;
 
-        let mut scanner = Scanner::new(source.to_owned());
-        scanner.scan_tokens();
-        assert!(!scanner.had_errors);
+        let mut scanner = Scanner::new(source);
+        let errors = scanner.scan_tokens();
+        assert!(errors.is_empty());
         assert_eq!(6, scanner.tokens.len())
     }
 
     #[test]
     fn end_of_stream_lookahead() {
-        let mut scanner = Scanner::new("<".to_owned());
-        scanner.scan_tokens();
-        assert!(!scanner.had_errors);
+        let mut scanner = Scanner::new("<");
+        let errors = scanner.scan_tokens();
+        assert!(errors.is_empty());
 
         let scanned_token = &scanner.tokens[0];
         assert_eq!(TokenKind::Less, scanned_token.kind);
     }
 
-
     #[test]
     fn comments() {
         let source = r#"
@@ -365,9 +960,9 @@ mod tests {
         {// a third comment
         "#;
 
-        let mut scanner = Scanner::new(source.to_owned());
-        scanner.scan_tokens();
-        assert!(!scanner.had_errors);
+        let mut scanner = Scanner::new(source);
+        let errors = scanner.scan_tokens();
+        assert!(errors.is_empty());
 
         assert_eq!(3, scanner.tokens.len());
     }
@@ -378,55 +973,110 @@ mod tests {
         "blop"
         "#;
 
-        let mut scanner = Scanner::new(source.to_owned());
-        scanner.scan_tokens();
-        assert!(!scanner.had_errors);
+        let mut scanner = Scanner::new(source);
+        let errors = scanner.scan_tokens();
+        assert!(errors.is_empty());
 
         let token = &scanner.tokens[0];
 
-        assert_eq!(&TokenKind::String("blop".to_owned()), &token.kind);
+        assert_eq!(&TokenKind::String(Symbol::intern("blop")), &token.kind);
         // Should the string literal lexeme contain the quotes?
-        // assert_eq!("blop", &token.lexeme);
+        // assert_eq!("blop", token.lexeme);
     }
 
     #[test]
     fn multi_line_string_literal() {
-        let source = String::from(r#"
+        let source = String::from(
+            r#"
         "blop
         blip"
-        "#);
-
-        let literal = String::from(r#"blop
-        blip"#);
-
-        let mut scanner = Scanner::new(source);
-        scanner.scan_tokens();
-        assert!(!scanner.had_errors);
-        assert_eq!(&TokenKind::String(literal), &scanner.tokens[0].kind);
+        "#,
+        );
+
+        let literal = String::from(
+            r#"blop
+        blip"#,
+        );
+
+        let mut scanner = Scanner::new(&source);
+        let errors = scanner.scan_tokens();
+        assert!(errors.is_empty());
+        assert_eq!(
+            &TokenKind::String(Symbol::intern(&literal)),
+            &scanner.tokens[0].kind
+        );
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn numbers() {
         let source = String::from("7 42 3.14 8A");
-        let mut scanner = Scanner::new(source);
-        scanner.scan_tokens();
-        assert!(!scanner.had_errors);
+        let mut scanner = Scanner::new(&source);
+        let errors = scanner.scan_tokens();
 
         assert_eq!(&TokenKind::Number(7.0), &scanner.tokens[0].kind);
         assert_eq!(&TokenKind::Number(42.0), &scanner.tokens[1].kind);
         assert_eq!(&TokenKind::Number(3.14), &scanner.tokens[2].kind);
 
         // 8A is not a number in rlox
-        assert_eq!(&TokenKind::Number(8.00), &scanner.tokens[3].kind);
-        assert_eq!(&TokenKind::Identifier, &scanner.tokens[4].kind);
+        assert_eq!(1, errors.len());
+        assert_eq!(ScanErrorKind::MalformedNumber, errors[0].kind);
+        assert_eq!(Span::new(10, 12), errors[0].span);
+        assert_eq!(&TokenKind::Eof, &scanner.tokens[3].kind);
+    }
+
+    #[test]
+    fn number_formats() {
+        let source = "16777217 0xFF 0Xff 0b1010 1_000_000 1.5e3 1e-9 2E+2 1_0.2_5";
+        let mut scanner = Scanner::new(source);
+        let errors = scanner.scan_tokens();
+        assert!(errors.is_empty());
+
+        let numbers: Vec<TokenKind> = vec![
+            16777217.0,
+            255.0,
+            255.0,
+            10.0,
+            1_000_000.0,
+            1500.0,
+            1e-9,
+            200.0,
+            10.25,
+        ]
+        .into_iter()
+        .map(TokenKind::Number)
+        .collect();
+        let kinds: Vec<TokenKind> = scanner.tokens.iter().map(|t| t.kind).collect();
+        assert_eq!(numbers, kinds[..numbers.len()]);
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        for source in [
+            "0x", "0b102", "0xG", "1_", "1__", "1_.5", "1e", "1e+", "1e_5", "12ab",
+        ] {
+            let mut scanner = Scanner::new(source);
+            let errors = scanner.scan_tokens();
+
+            assert_eq!(1, errors.len(), "{}", source);
+            assert_eq!(ScanErrorKind::MalformedNumber, errors[0].kind);
+            assert_eq!("Malformed number.", errors[0].message);
+            // The whole literal is skipped.
+            assert_eq!(source.len(), errors[0].span.len(), "{}", source);
+        }
+
+        // A method call on a number is not a fraction.
+        let mut scanner = Scanner::new("1.foo");
+        assert!(scanner.scan_tokens().is_empty());
+        assert_eq!(TokenKind::Dot, scanner.tokens[1].kind);
     }
 
     #[test]
     fn parenthesis() {
         let source = String::from("(42");
-        let mut scanner = Scanner::new(source);
-        scanner.scan_tokens();
-        assert!(!scanner.had_errors);
+        let mut scanner = Scanner::new(&source);
+        let errors = scanner.scan_tokens();
+        assert!(errors.is_empty());
 
         assert_eq!(&TokenKind::LeftParen, &scanner.tokens[0].kind);
         assert_eq!(&TokenKind::Number(42.0), &scanner.tokens[1].kind);
@@ -435,15 +1085,378 @@ mod tests {
     #[test]
     fn identifiers() {
         let source = String::from("or k8s _blop var counter");
-        let mut scanner = Scanner::new(source);
-        scanner.scan_tokens();
-        assert!(!scanner.had_errors);
+        let mut scanner = Scanner::new(&source);
+        let errors = scanner.scan_tokens();
+        assert!(errors.is_empty());
 
         assert_eq!(&TokenKind::Or, &scanner.tokens[0].kind);
-        assert_eq!(&TokenKind::Identifier, &scanner.tokens[1].kind);
-        assert_eq!(&TokenKind::Identifier, &scanner.tokens[2].kind);
+        let identifier = |name| TokenKind::Identifier(Symbol::intern(name));
+        assert_eq!(identifier("k8s"), scanner.tokens[1].kind);
+        assert_eq!(identifier("_blop"), scanner.tokens[2].kind);
         assert_eq!(&TokenKind::Var, &scanner.tokens[3].kind);
-        assert_eq!(&TokenKind::Identifier, &scanner.tokens[4].kind);
+        assert_eq!(identifier("counter"), scanner.tokens[4].kind);
         assert_eq!(&TokenKind::Eof, &scanner.tokens[5].kind);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn incomplete_input() {
+        assert!(Scanner::is_incomplete("fun f() {"));
+        assert!(Scanner::is_incomplete("print (1 +"));
+        assert!(Scanner::is_incomplete("print \"abc"));
+        assert!(Scanner::is_incomplete("{ { } // }"));
+
+        assert!(!Scanner::is_incomplete("fun f() {}"));
+        assert!(!Scanner::is_incomplete("print \"{\";"));
+        // Too many closing braces can't be fixed by more input.
+        assert!(!Scanner::is_incomplete("}"));
+    }
+
+    #[test]
+    fn identifiers_with_underscores() {
+        let mut scanner = Scanner::new("make_counter");
+        let errors = scanner.scan_tokens();
+        assert!(errors.is_empty());
+
+        assert_eq!(2, scanner.tokens.len());
+        assert_eq!("make_counter", scanner.tokens[0].lexeme);
+    }
+
+    #[test]
+    fn unicode() {
+        let source = String::from(
+            r#"
+            // Un commentaire en français, avec des accents: é à ü 😀
+            var café = "naïve 😀";
+            "#,
+        );
+        let mut scanner = Scanner::new(&source);
+        let errors = scanner.scan_tokens();
+        assert!(errors.is_empty());
+
+        assert_eq!(&TokenKind::Var, &scanner.tokens[0].kind);
+        assert_eq!("café", scanner.tokens[1].lexeme);
+        assert_eq!(
+            &TokenKind::Identifier(Symbol::intern("café")),
+            &scanner.tokens[1].kind
+        );
+        assert_eq!(
+            &TokenKind::String(Symbol::intern("naïve 😀")),
+            &scanner.tokens[3].kind
+        );
+        assert_eq!("\"naïve 😀\"", scanner.tokens[3].lexeme);
+        assert_eq!(3, scanner.tokens[4].line);
+    }
+
+    #[test]
+    fn unexpected_unicode_character() {
+        let mut scanner = Scanner::new("1 € 2");
+        let errors = scanner.scan_tokens();
+
+        assert_eq!(1, errors.len());
+        assert_eq!(ScanErrorKind::UnexpectedCharacter('€'), errors[0].kind);
+        assert_eq!(3, errors[0].column);
+        assert_eq!(Span::new(2, 5), errors[0].span);
+
+        assert_eq!(&TokenKind::Number(1.0), &scanner.tokens[0].kind);
+        assert_eq!(&TokenKind::Number(2.0), &scanner.tokens[1].kind);
+    }
+
+    #[test]
+    fn large_source() {
+        // Would take ages if looking ahead was not constant time.
+        let source = "var a = \"é\" + 1.5;\n".repeat(50_000);
+        let mut scanner = Scanner::new(&source);
+        let errors = scanner.scan_tokens();
+        assert!(errors.is_empty());
+
+        assert_eq!(7 * 50_000 + 1, scanner.tokens.len());
+        assert_eq!(50_001, scanner.tokens.last().unwrap().line);
+    }
+
+    #[test]
+    fn unterminated_string() {
+        let mut scanner = Scanner::new("1;\n  \"abc\n");
+        let errors = scanner.scan_tokens();
+
+        assert_eq!(
+            vec![ScanError {
+                kind: ScanErrorKind::UnterminatedString,
+                line: 2,
+                column: 3,
+                span: Span::new(5, 10),
+                message: "Unterminated string.".to_owned(),
+            }],
+            errors
+        );
+        assert_eq!(
+            "[line 2] Error: Unterminated string.",
+            errors[0].to_string()
+        );
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let mut scanner = Scanner::new("@ 1\n# 2");
+        let errors = scanner.scan_tokens();
+
+        assert_eq!(2, errors.len());
+        assert_eq!((1, 1), (errors[0].line, errors[0].column));
+        assert_eq!((2, 1), (errors[1].line, errors[1].column));
+        // Valid tokens are still produced.
+        assert_eq!(3, scanner.tokens.len());
+    }
+
+    #[test]
+    fn positions() {
+        let mut scanner = Scanner::new("var é = \"a\nb\";\n  print é;");
+        let errors = scanner.scan_tokens();
+        assert!(errors.is_empty());
+
+        let positions: Vec<(usize, usize, Span)> = scanner
+            .tokens
+            .iter()
+            .map(|token| (token.line, token.column, token.span))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (1, 1, Span::new(0, 3)),
+                (1, 5, Span::new(4, 6)),
+                (1, 7, Span::new(7, 8)),
+                // A multi-line string is located at its opening quote.
+                (1, 9, Span::new(9, 14)),
+                (2, 3, Span::new(14, 15)),
+                (3, 3, Span::new(18, 23)),
+                (3, 9, Span::new(24, 26)),
+                (3, 10, Span::new(26, 27)),
+                (3, 11, Span::new(27, 27)),
+            ],
+            positions
+        );
+    }
+
+    #[test]
+    fn tokens_borrow_from_source() {
+        let source = "print a;";
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens();
+
+        assert!(std::ptr::eq(&source[0..5], scanner.tokens[0].lexeme));
+    }
+
+    #[test]
+    fn interned() {
+        let mut scanner = Scanner::new("a \"a\" a \"\\u{61}\"");
+        scanner.scan_tokens();
+        let tokens = &scanner.tokens;
+
+        assert_eq!(tokens[0].kind, tokens[2].kind);
+        assert_eq!(tokens[1].kind, tokens[3].kind);
+        assert_eq!(TokenKind::String(Symbol::intern("a")), tokens[1].kind);
+        assert_eq!(Symbol::intern("a"), tokens[0].symbol());
+    }
+
+    #[test]
+    fn string_escapes() {
+        let source = r#""a\tb\n\"c\"\\\0\u{e9}\u{1F600}""#;
+        let mut scanner = Scanner::new(source);
+        let errors = scanner.scan_tokens();
+        assert!(errors.is_empty());
+
+        let token = &scanner.tokens[0];
+        assert_eq!(
+            TokenKind::String(Symbol::intern("a\tb\n\"c\"\\\0é😀")),
+            token.kind
+        );
+        assert_eq!(source, token.lexeme);
+    }
+
+    #[test]
+    fn escaped_quote_does_not_end_string() {
+        assert!(Scanner::is_incomplete(r#"print "abc\""#));
+    }
+
+    #[test]
+    fn invalid_escapes() {
+        let source = r#"print "a\qb\u{D800}\u{}\u12";"#;
+        let mut scanner = Scanner::new(source);
+        let errors = scanner.scan_tokens();
+
+        let kinds: Vec<ScanErrorKind> = errors.iter().map(|error| error.kind.clone()).collect();
+        assert_eq!(
+            vec![
+                ScanErrorKind::UnknownEscape('q'),
+                ScanErrorKind::InvalidCodePoint(0xD800),
+                ScanErrorKind::InvalidUnicodeEscape,
+                ScanErrorKind::InvalidUnicodeEscape,
+            ],
+            kinds
+        );
+        assert_eq!("Unknown escape sequence '\\q'.", errors[0].message);
+        assert_eq!(Span::new(8, 10), errors[0].span);
+        assert_eq!(9, errors[0].column);
+        assert_eq!(
+            r"\u{D800}",
+            &source[errors[1].span.start..errors[1].span.end]
+        );
+
+        // The string and the rest of the line are still scanned.
+        assert_eq!(
+            TokenKind::String(Symbol::intern("ab12")),
+            scanner.tokens[1].kind
+        );
+        assert_eq!(TokenKind::SemiColon, scanner.tokens[2].kind);
+    }
+
+    #[test]
+    fn block_comments() {
+        let source = "1 /* a\n/* nested\n*/ still */ 2 /**/ 3";
+        let mut scanner = Scanner::new(source);
+        let errors = scanner.scan_tokens();
+        assert!(errors.is_empty());
+
+        let kinds: Vec<&TokenKind> = scanner.tokens.iter().map(|token| &token.kind).collect();
+        assert_eq!(
+            vec![
+                &TokenKind::Number(1.0),
+                &TokenKind::Number(2.0),
+                &TokenKind::Number(3.0),
+                &TokenKind::Eof
+            ],
+            kinds
+        );
+        assert_eq!(3, scanner.tokens[1].line);
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        let mut scanner = Scanner::new("1;\n  /* a /* b */\n c");
+        let errors = scanner.scan_tokens();
+
+        assert_eq!(1, errors.len());
+        assert_eq!(ScanErrorKind::UnterminatedBlockComment, errors[0].kind);
+        assert_eq!("Unterminated block comment.", errors[0].message);
+        assert_eq!((2, 3), (errors[0].line, errors[0].column));
+        assert!(Scanner::is_incomplete("/* a"));
+    }
+
+    #[test]
+    fn piece_of_larger_text() {
+        let mut scanner = Scanner::starting_at("a\n @", 10, 4);
+        let errors = scanner.scan_tokens();
+
+        let token = &scanner.tokens[0];
+        assert_eq!(
+            (4, 1, Span::new(10, 11)),
+            (token.line, token.column, token.span)
+        );
+        let error = &errors[0];
+        assert_eq!(
+            (5, 2, Span::new(13, 14)),
+            (error.line, error.column, error.span)
+        );
+        assert_eq!(Span::new(14, 14), scanner.tokens[1].span);
+    }
+
+    /// Gives one chunk per read, like a pipe. An empty chunk closes the
+    /// pipe, waiting for more chunks than were sent fails the test.
+    struct Pipe(VecDeque<&'static str>);
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let chunk = *self.0.front().expect("Waited for more input");
+            if !chunk.is_empty() {
+                self.0.pop_front();
+            }
+            buf[..chunk.len()].copy_from_slice(chunk.as_bytes());
+            Ok(chunk.len())
+        }
+    }
+
+    #[test]
+    fn stream() {
+        let chunks = ["var a = \"x\n", "y\";\n/* c\n", "*/ print a;", ""];
+        let source_map = SourceMap::default();
+        let streamed: Vec<Token> = StreamScanner::new(Pipe(chunks.into()), &source_map)
+            .map(Result::unwrap)
+            .collect();
+
+        let source = chunks.concat();
+        let scanned: Vec<Token> = Scanner::new(&source).map(Result::unwrap).collect();
+        let positions = |tokens: &[Token]| -> Vec<(TokenKind, usize, usize)> {
+            tokens
+                .iter()
+                .map(|token| (token.kind, token.line, token.column))
+                .collect()
+        };
+        assert_eq!(positions(&scanned), positions(&streamed));
+
+        // Pieces don't end inside the string, nor the comment.
+        assert_eq!(Some("\"x\ny\""), source_map.snippet(streamed[3].span));
+        assert_eq!(
+            Some("*/ print a;"),
+            source_map.line_at(streamed[5].span.start)
+        );
+        assert_eq!(
+            Some((4, 12)),
+            source_map.line_column(streamed[8].span.start)
+        );
+    }
+
+    #[test]
+    fn open_lexeme_at_end() {
+        let state = |source: &str| {
+            source
+                .bytes()
+                .fold(LexemeState::Code, |state, byte| state.next(byte))
+        };
+        assert_eq!(LexemeState::Code, state("print \"a\\\"b\"; // \"\n"));
+        assert_eq!(LexemeState::String, state("print \"a\\\"b;\n"));
+        assert_eq!(LexemeState::BlockComment(1), state("1 /* a /* b */ \"\n"));
+        assert_eq!(LexemeState::Code, state("1 /* a /* b */ */ 2 / 3\n"));
+        assert_eq!(LexemeState::Code, state("/**/ 2 /\"/\"\n"));
+    }
+
+    #[test]
+    fn stream_piece_too_long() {
+        let source = format!("print \"{}", "a\n".repeat(MAX_PIECE_SIZE));
+        let source_map = SourceMap::default();
+        let mut scanner = StreamScanner::new(source.as_bytes(), &source_map);
+
+        let kinds: Vec<TokenKind> = scanner.by_ref().map(|t| t.unwrap().kind).collect();
+        assert_eq!(vec![TokenKind::Eof], kinds);
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            scanner.take_error().unwrap().kind()
+        );
+    }
+
+    #[test]
+    fn stream_before_end_of_input() {
+        let source_map = SourceMap::default();
+        let mut scanner = StreamScanner::new(Pipe(["print 1;\n"].into()), &source_map);
+
+        let kinds: Vec<TokenKind> = scanner.by_ref().take(3).map(|t| t.unwrap().kind).collect();
+        assert_eq!(
+            vec![
+                TokenKind::Print,
+                TokenKind::Number(1.0),
+                TokenKind::SemiColon
+            ],
+            kinds
+        );
+    }
+
+    #[test]
+    fn stream_error() {
+        let source_map = SourceMap::default();
+        let mut scanner = StreamScanner::new(&[b'"', 0xFF][..], &source_map);
+
+        let kinds: Vec<TokenKind> = scanner.by_ref().map(|t| t.unwrap().kind).collect();
+        assert_eq!(vec![TokenKind::Eof], kinds);
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            scanner.take_error().unwrap().kind()
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use std::cell::{Cell, OnceCell};
+use std::fmt;
+
+/// Byte offsets of a piece of source: `start` is included, `end` excluded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// Span going from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// Number of chunks holding the pieces of a `SourceMap`.
+const CHUNKS: usize = 32;
+
+/// Holds the source and converts byte offsets to lines and columns. Lines
+/// and columns start at 1, columns are counted in chars.
+///
+/// The source can be made of several pieces, like the inputs of the REPL.
+/// They share one offset space: each piece starts one byte after the end of
+/// the previous one, so that any offset, even the one right after a piece,
+/// belongs to a single piece.
+///
+/// Pieces are added through a shared reference and never move, so text
+/// handed out by `add` stays borrowed while more is added. They are kept in
+/// chunks that are never reallocated, chunk `i` having room for `2^i`
+/// pieces: adding one takes constant time and finding the piece of an
+/// offset is a binary search.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    chunks: [OnceCell<Box<[OnceCell<Piece>]>>; CHUNKS],
+    len: Cell<usize>,
+}
+
+#[derive(Debug)]
+struct Piece {
+    /// Offset of the first byte of `text`.
+    start: usize,
+    text: String,
+    /// Number of the first line of `text`.
+    first_line: usize,
+    /// Byte offset in `text` of the first char of each line.
+    line_starts: Vec<usize>,
+}
+
+impl Piece {
+    /// Index in `line_starts` of the line containing `offset`.
+    fn line_index(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(index) => index,
+            Err(index) => index - 1,
+        }
+    }
+}
+
+/// Chunk holding the piece at `index`, and its slot in the chunk.
+fn chunk_slot(index: usize) -> (usize, usize) {
+    let chunk = (index + 1).ilog2() as usize;
+    (chunk, index + 1 - (1 << chunk))
+}
+
+impl SourceMap {
+    pub fn new(source: &str) -> Self {
+        let source_map = SourceMap::default();
+        source_map.add(source.to_owned(), 1);
+        source_map
+    }
+
+    /// Adds a piece of source, whose lines are numbered from `first_line`.
+    /// Returns the offset it starts at and the text, to be scanned with
+    /// `Scanner::starting_at`.
+    pub fn add(&self, text: String, first_line: usize) -> (usize, &str) {
+        let index = self.len.get();
+        let start = match index.checked_sub(1) {
+            Some(last) => {
+                let last = self.piece(last);
+                last.start + last.text.len() + 1
+            }
+            None => 0,
+        };
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        let (chunk, slot) = chunk_slot(index);
+        assert!(chunk < CHUNKS, "Too many pieces in the source map");
+        let chunk =
+            self.chunks[chunk].get_or_init(|| (0..1 << chunk).map(|_| OnceCell::new()).collect());
+        let piece = chunk[slot].get_or_init(|| Piece {
+            start,
+            text,
+            first_line,
+            line_starts,
+        });
+        self.len.set(index + 1);
+
+        (start, &piece.text)
+    }
+
+    /// Piece at `index`, which must be less than `len`.
+    fn piece(&self, index: usize) -> &Piece {
+        let (chunk, slot) = chunk_slot(index);
+        self.chunks[chunk]
+            .get()
+            .and_then(|chunk| chunk[slot].get())
+            .expect("Pieces below len are added")
+    }
+
+    /// Piece containing `offset`, and `offset` within it. Offsets between
+    /// pieces or in the middle of a char have none.
+    fn locate(&self, offset: usize) -> Option<(&Piece, usize)> {
+        // Number of pieces starting at or before `offset`.
+        let (mut low, mut high) = (0, self.len.get());
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if self.piece(middle).start <= offset {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+
+        let piece = self.piece(low.checked_sub(1)?);
+        let offset = offset - piece.start;
+
+        piece
+            .text
+            .is_char_boundary(offset)
+            .then_some((piece, offset))
+    }
+
+    /// Line and column of the char at `offset`. The end of a piece is right
+    /// after its last char.
+    pub fn line_column(&self, offset: usize) -> Option<(usize, usize)> {
+        let (piece, offset) = self.locate(offset)?;
+        let index = piece.line_index(offset);
+        let column = piece.text[piece.line_starts[index]..offset].chars().count() + 1;
+
+        Some((piece.first_line + index, column))
+    }
+
+    /// Text of the line containing `offset`, without its line terminator.
+    pub fn line_at(&self, offset: usize) -> Option<&str> {
+        let (piece, offset) = self.locate(offset)?;
+        let index = piece.line_index(offset);
+        let start = piece.line_starts[index];
+        let end = piece
+            .line_starts
+            .get(index + 1)
+            .map_or(piece.text.len(), |next| next - 1);
+
+        Some(piece.text[start..end].trim_end_matches('\r'))
+    }
+
+    /// Source text covered by `span`, if it lies within a single piece.
+    pub fn snippet(&self, span: Span) -> Option<&str> {
+        let (piece, start) = self.locate(span.start)?;
+        piece.text.get(start..span.end - piece.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_spans() {
+        assert_eq!(Span::new(2, 9), Span::new(2, 4).to(Span::new(7, 9)));
+        assert_eq!(Span::new(2, 9), Span::new(7, 9).to(Span::new(2, 4)));
+    }
+
+    #[test]
+    fn line_column() {
+        let map = SourceMap::new("var a;\nprint é + b;\n");
+
+        assert_eq!(Some((1, 1)), map.line_column(0));
+        assert_eq!(Some((1, 5)), map.line_column(4));
+        assert_eq!(Some((2, 1)), map.line_column(7));
+        // 'é' takes two bytes but one column.
+        assert_eq!(Some((2, 9)), map.line_column(16));
+        assert_eq!(None, map.line_column(14));
+        assert_eq!(Some((3, 1)), map.line_column(21));
+        assert_eq!(None, map.line_column(100));
+    }
+
+    #[test]
+    fn lines() {
+        let map = SourceMap::new("first\r\nsecond\nthird");
+
+        assert_eq!(Some("first"), map.line_at(0));
+        assert_eq!(Some("second"), map.line_at(9));
+        assert_eq!(Some("third"), map.line_at(18));
+        assert_eq!(Some("sec"), map.snippet(Span::new(7, 10)));
+        assert_eq!(None, map.snippet(Span::new(7, 100)));
+    }
+
+    #[test]
+    fn pieces() {
+        let map = SourceMap::new("var a = 1;\n");
+        let (start, text) = map.add("print a;".to_owned(), 1);
+        assert_eq!(12, start);
+        assert_eq!(21, map.add("x\ny".to_owned(), 7).0);
+        // Added text stays borrowed while more is added.
+        assert_eq!("print a;", text);
+
+        assert_eq!(Some((1, 7)), map.line_column(18));
+        assert_eq!(Some("print a;"), map.line_at(18));
+        // Right after the end of a piece.
+        assert_eq!(Some((1, 9)), map.line_column(20));
+        assert_eq!(Some((8, 1)), map.line_column(23));
+        assert_eq!(Some("a;"), map.snippet(Span::new(18, 20)));
+        // Spans can't go from one piece to the next.
+        assert_eq!(None, map.snippet(Span::new(18, 22)));
+    }
+
+    #[test]
+    fn many_pieces() {
+        let map = SourceMap::default();
+        let starts: Vec<usize> = (1..=1000)
+            .map(|line| map.add(format!("print {};", line), line).0)
+            .collect();
+
+        for (line, start) in (1..).zip(starts) {
+            assert_eq!(Some((line, 7)), map.line_column(start + 6));
+        }
+    }
+}
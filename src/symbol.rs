@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, OnceLock};
+
+/// An interned string. Identifiers and string literals are stored once in a
+/// table shared by the scanner, the parser and the interpreter, a symbol is
+/// their index in it. Comparing and hashing symbols never looks at the text.
+///
+/// The table is process-wide and only grows: what was interned stays until
+/// the process exits, even once no session uses it anymore. A symbol keeps
+/// a reference to its text, so only interning takes the table's lock.
+#[derive(Clone, Copy)]
+pub struct Symbol {
+    index: u32,
+    text: &'static str,
+}
+
+/// Interned first, in this order, so that their symbols are constants.
+const PREDEFINED: [&str; 4] = ["", "init", "this", "super"];
+
+impl Symbol {
+    pub const EMPTY: Symbol = Symbol::predefined(0);
+    pub const INIT: Symbol = Symbol::predefined(1);
+    pub const THIS: Symbol = Symbol::predefined(2);
+    pub const SUPER: Symbol = Symbol::predefined(3);
+
+    const fn predefined(index: usize) -> Symbol {
+        Symbol {
+            index: index as u32,
+            text: PREDEFINED[index],
+        }
+    }
+
+    pub fn intern(text: &str) -> Symbol {
+        interner().lock().unwrap().intern(text)
+    }
+
+    /// Interned strings are never freed, the text lives as long as the
+    /// program.
+    pub fn as_str(self) -> &'static str {
+        self.text
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Symbol({}, {:?})", self.index, self.text)
+    }
+}
+
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+}
+
+impl Interner {
+    fn intern(&mut self, text: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(text) {
+            return symbol;
+        }
+
+        self.insert(Box::leak(text.into()))
+    }
+
+    fn insert(&mut self, text: &'static str) -> Symbol {
+        let symbol = Symbol {
+            index: self.symbols.len() as u32,
+            text,
+        };
+        self.symbols.insert(text, symbol);
+
+        symbol
+    }
+}
+
+fn interner() -> &'static Mutex<Interner> {
+    static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+
+    INTERNER.get_or_init(|| {
+        let mut interner = Interner::default();
+        for text in PREDEFINED {
+            interner.insert(text);
+        }
+        Mutex::new(interner)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern_once() {
+        let symbol = Symbol::intern("interned");
+
+        assert_eq!(symbol, Symbol::intern("interned"));
+        assert_ne!(symbol, Symbol::intern("other"));
+        assert_eq!("interned", symbol.as_str());
+    }
+
+    #[test]
+    fn predefined() {
+        assert_eq!(Symbol::INIT, Symbol::intern("init"));
+        assert_eq!(Symbol::THIS, Symbol::intern("this"));
+        assert_eq!(Symbol::SUPER, Symbol::intern("super"));
+        assert_eq!("", Symbol::EMPTY.as_str());
+    }
+}
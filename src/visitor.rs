@@ -0,0 +1,994 @@
+use crate::parser::{Ast, ExprId, ExprKind, FunctionId, StmtId, StmtKind};
+use crate::scanner::Token;
+
+/// Visits expressions, with a method for each kind. A pass only overrides
+/// the kinds it cares about, the others visit their children by default and
+/// merge what they return with `combine`. Leaves return `R::default()`.
+pub trait ExprVisitor<'src, R: Default = ()> {
+    /// Calls the method for the kind of `expr`.
+    fn visit_expr(&mut self, ast: &Ast<'src>, expr: ExprId) -> R {
+        walk_expr(self, ast, expr)
+    }
+
+    /// Merges the results of two children in the default traversal. Keeps
+    /// the second one unless overridden.
+    fn combine(&mut self, _first: R, second: R) -> R {
+        second
+    }
+
+    fn visit_literal(&mut self, _ast: &Ast<'src>, _expr: ExprId, _value: &Token<'src>) -> R {
+        R::default()
+    }
+
+    fn visit_unary(
+        &mut self,
+        ast: &Ast<'src>,
+        _expr: ExprId,
+        _operator: &Token<'src>,
+        right: ExprId,
+    ) -> R {
+        walk_unary(self, ast, right)
+    }
+
+    fn visit_binary(
+        &mut self,
+        ast: &Ast<'src>,
+        _expr: ExprId,
+        left: ExprId,
+        _operator: &Token<'src>,
+        right: ExprId,
+    ) -> R {
+        walk_binary(self, ast, left, right)
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast<'src>, _expr: ExprId, inner: ExprId) -> R {
+        walk_grouping(self, ast, inner)
+    }
+
+    fn visit_variable(&mut self, _ast: &Ast<'src>, _expr: ExprId, _name: &Token<'src>) -> R {
+        R::default()
+    }
+
+    fn visit_assign(
+        &mut self,
+        ast: &Ast<'src>,
+        _expr: ExprId,
+        _name: &Token<'src>,
+        value: ExprId,
+    ) -> R {
+        walk_assign(self, ast, value)
+    }
+
+    fn visit_logical(
+        &mut self,
+        ast: &Ast<'src>,
+        _expr: ExprId,
+        left: ExprId,
+        _operator: &Token<'src>,
+        right: ExprId,
+    ) -> R {
+        walk_logical(self, ast, left, right)
+    }
+
+    fn visit_call(
+        &mut self,
+        ast: &Ast<'src>,
+        _expr: ExprId,
+        callee: ExprId,
+        _paren: &Token<'src>,
+        arguments: &[ExprId],
+    ) -> R {
+        walk_call(self, ast, callee, arguments)
+    }
+
+    fn visit_get(
+        &mut self,
+        ast: &Ast<'src>,
+        _expr: ExprId,
+        object: ExprId,
+        _name: &Token<'src>,
+    ) -> R {
+        walk_get(self, ast, object)
+    }
+
+    fn visit_set(
+        &mut self,
+        ast: &Ast<'src>,
+        _expr: ExprId,
+        object: ExprId,
+        _name: &Token<'src>,
+        value: ExprId,
+    ) -> R {
+        walk_set(self, ast, object, value)
+    }
+
+    fn visit_this(&mut self, _ast: &Ast<'src>, _expr: ExprId, _keyword: &Token<'src>) -> R {
+        R::default()
+    }
+
+    fn visit_super(
+        &mut self,
+        _ast: &Ast<'src>,
+        _expr: ExprId,
+        _keyword: &Token<'src>,
+        _method: &Token<'src>,
+    ) -> R {
+        R::default()
+    }
+}
+
+/// Visits statements and the expressions they contain, like `ExprVisitor`.
+pub trait StmtVisitor<'src, R: Default = ()>: ExprVisitor<'src, R> {
+    /// Calls the method for the kind of `stmt`.
+    fn visit_stmt(&mut self, ast: &Ast<'src>, stmt: StmtId) -> R {
+        walk_stmt(self, ast, stmt)
+    }
+
+    fn visit_expression_stmt(&mut self, ast: &Ast<'src>, _stmt: StmtId, expr: ExprId) -> R {
+        walk_expression_stmt(self, ast, expr)
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast<'src>, _stmt: StmtId, expr: ExprId) -> R {
+        walk_print_stmt(self, ast, expr)
+    }
+
+    fn visit_var_stmt(
+        &mut self,
+        ast: &Ast<'src>,
+        _stmt: StmtId,
+        _name: &Token<'src>,
+        initializer: Option<ExprId>,
+    ) -> R {
+        walk_var_stmt(self, ast, initializer)
+    }
+
+    fn visit_block_stmt(&mut self, ast: &Ast<'src>, _stmt: StmtId, statements: &[StmtId]) -> R {
+        walk_block_stmt(self, ast, statements)
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        ast: &Ast<'src>,
+        _stmt: StmtId,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    ) -> R {
+        walk_if_stmt(self, ast, condition, then_branch, else_branch)
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        ast: &Ast<'src>,
+        _stmt: StmtId,
+        condition: ExprId,
+        body: StmtId,
+    ) -> R {
+        walk_while_stmt(self, ast, condition, body)
+    }
+
+    fn visit_function_stmt(&mut self, ast: &Ast<'src>, _stmt: StmtId, function: FunctionId) -> R {
+        walk_function_stmt(self, ast, function)
+    }
+
+    fn visit_return_stmt(
+        &mut self,
+        ast: &Ast<'src>,
+        _stmt: StmtId,
+        _keyword: &Token<'src>,
+        value: Option<ExprId>,
+    ) -> R {
+        walk_return_stmt(self, ast, value)
+    }
+
+    fn visit_class_stmt(
+        &mut self,
+        ast: &Ast<'src>,
+        _stmt: StmtId,
+        _name: &Token<'src>,
+        superclass: Option<ExprId>,
+        methods: &[FunctionId],
+    ) -> R {
+        walk_class_stmt(self, ast, superclass, methods)
+    }
+
+    /// Functions and methods.
+    fn visit_function(&mut self, ast: &Ast<'src>, function: FunctionId) -> R {
+        walk_function(self, ast, function)
+    }
+}
+
+/// Visits `exprs` in order and combines what they return.
+fn visit_exprs<'src, R, V>(
+    visitor: &mut V,
+    ast: &Ast<'src>,
+    exprs: impl IntoIterator<Item = ExprId>,
+) -> R
+where
+    R: Default,
+    V: ExprVisitor<'src, R> + ?Sized,
+{
+    let mut result = R::default();
+    for expr in exprs {
+        let next = visitor.visit_expr(ast, expr);
+        result = visitor.combine(result, next);
+    }
+
+    result
+}
+
+/// Visits `stmts` in order and combines what they return.
+fn visit_stmts<'src, R, V>(
+    visitor: &mut V,
+    ast: &Ast<'src>,
+    stmts: impl IntoIterator<Item = StmtId>,
+) -> R
+where
+    R: Default,
+    V: StmtVisitor<'src, R> + ?Sized,
+{
+    let mut result = R::default();
+    for stmt in stmts {
+        let next = visitor.visit_stmt(ast, stmt);
+        result = visitor.combine(result, next);
+    }
+
+    result
+}
+
+/// Calls the method of `visitor` for the kind of `expr`.
+pub fn walk_expr<'src, R, V>(visitor: &mut V, ast: &Ast<'src>, expr: ExprId) -> R
+where
+    R: Default,
+    V: ExprVisitor<'src, R> + ?Sized,
+{
+    match &ast[expr].kind {
+        ExprKind::Literal(value) => visitor.visit_literal(ast, expr, value),
+        ExprKind::Unary(operator, right) => visitor.visit_unary(ast, expr, operator, *right),
+        ExprKind::Binary(left, operator, right) => {
+            visitor.visit_binary(ast, expr, *left, operator, *right)
+        }
+        ExprKind::Grouping(inner) => visitor.visit_grouping(ast, expr, *inner),
+        ExprKind::Variable(name) => visitor.visit_variable(ast, expr, name),
+        ExprKind::Assign(name, value) => visitor.visit_assign(ast, expr, name, *value),
+        ExprKind::Logical(left, operator, right) => {
+            visitor.visit_logical(ast, expr, *left, operator, *right)
+        }
+        ExprKind::Call(callee, paren, arguments) => {
+            visitor.visit_call(ast, expr, *callee, paren, arguments)
+        }
+        ExprKind::Get(object, name) => visitor.visit_get(ast, expr, *object, name),
+        ExprKind::Set(object, name, value) => visitor.visit_set(ast, expr, *object, name, *value),
+        ExprKind::This(keyword) => visitor.visit_this(ast, expr, keyword),
+        ExprKind::Super(keyword, method) => visitor.visit_super(ast, expr, keyword, method),
+    }
+}
+
+pub fn walk_unary<'src, R, V>(visitor: &mut V, ast: &Ast<'src>, right: ExprId) -> R
+where
+    R: Default,
+    V: ExprVisitor<'src, R> + ?Sized,
+{
+    visitor.visit_expr(ast, right)
+}
+
+pub fn walk_binary<'src, R, V>(visitor: &mut V, ast: &Ast<'src>, left: ExprId, right: ExprId) -> R
+where
+    R: Default,
+    V: ExprVisitor<'src, R> + ?Sized,
+{
+    visit_exprs(visitor, ast, [left, right])
+}
+
+pub fn walk_grouping<'src, R, V>(visitor: &mut V, ast: &Ast<'src>, inner: ExprId) -> R
+where
+    R: Default,
+    V: ExprVisitor<'src, R> + ?Sized,
+{
+    visitor.visit_expr(ast, inner)
+}
+
+pub fn walk_assign<'src, R, V>(visitor: &mut V, ast: &Ast<'src>, value: ExprId) -> R
+where
+    R: Default,
+    V: ExprVisitor<'src, R> + ?Sized,
+{
+    visitor.visit_expr(ast, value)
+}
+
+pub fn walk_logical<'src, R, V>(visitor: &mut V, ast: &Ast<'src>, left: ExprId, right: ExprId) -> R
+where
+    R: Default,
+    V: ExprVisitor<'src, R> + ?Sized,
+{
+    visit_exprs(visitor, ast, [left, right])
+}
+
+/// Visits the callee, then the arguments.
+pub fn walk_call<'src, R, V>(
+    visitor: &mut V,
+    ast: &Ast<'src>,
+    callee: ExprId,
+    arguments: &[ExprId],
+) -> R
+where
+    R: Default,
+    V: ExprVisitor<'src, R> + ?Sized,
+{
+    visit_exprs(
+        visitor,
+        ast,
+        std::iter::once(callee).chain(arguments.iter().copied()),
+    )
+}
+
+pub fn walk_get<'src, R, V>(visitor: &mut V, ast: &Ast<'src>, object: ExprId) -> R
+where
+    R: Default,
+    V: ExprVisitor<'src, R> + ?Sized,
+{
+    visitor.visit_expr(ast, object)
+}
+
+/// Visits the object, then the value.
+pub fn walk_set<'src, R, V>(visitor: &mut V, ast: &Ast<'src>, object: ExprId, value: ExprId) -> R
+where
+    R: Default,
+    V: ExprVisitor<'src, R> + ?Sized,
+{
+    visit_exprs(visitor, ast, [object, value])
+}
+
+/// Calls the method of `visitor` for the kind of `stmt`.
+pub fn walk_stmt<'src, R, V>(visitor: &mut V, ast: &Ast<'src>, stmt: StmtId) -> R
+where
+    R: Default,
+    V: StmtVisitor<'src, R> + ?Sized,
+{
+    match &ast[stmt].kind {
+        StmtKind::Expression(expr) => visitor.visit_expression_stmt(ast, stmt, *expr),
+        StmtKind::Print(expr) => visitor.visit_print_stmt(ast, stmt, *expr),
+        StmtKind::Var(name, initializer) => visitor.visit_var_stmt(ast, stmt, name, *initializer),
+        StmtKind::Block(statements) => visitor.visit_block_stmt(ast, stmt, statements),
+        StmtKind::If(condition, then_branch, else_branch) => {
+            visitor.visit_if_stmt(ast, stmt, *condition, *then_branch, *else_branch)
+        }
+        StmtKind::While(condition, body) => visitor.visit_while_stmt(ast, stmt, *condition, *body),
+        StmtKind::Function(function) => visitor.visit_function_stmt(ast, stmt, *function),
+        StmtKind::Return(keyword, value) => visitor.visit_return_stmt(ast, stmt, keyword, *value),
+        StmtKind::Class(name, superclass, methods) => {
+            visitor.visit_class_stmt(ast, stmt, name, *superclass, methods)
+        }
+    }
+}
+
+pub fn walk_expression_stmt<'src, R, V>(visitor: &mut V, ast: &Ast<'src>, expr: ExprId) -> R
+where
+    R: Default,
+    V: StmtVisitor<'src, R> + ?Sized,
+{
+    visitor.visit_expr(ast, expr)
+}
+
+pub fn walk_print_stmt<'src, R, V>(visitor: &mut V, ast: &Ast<'src>, expr: ExprId) -> R
+where
+    R: Default,
+    V: StmtVisitor<'src, R> + ?Sized,
+{
+    visitor.visit_expr(ast, expr)
+}
+
+pub fn walk_var_stmt<'src, R, V>(visitor: &mut V, ast: &Ast<'src>, initializer: Option<ExprId>) -> R
+where
+    R: Default,
+    V: StmtVisitor<'src, R> + ?Sized,
+{
+    visit_exprs(visitor, ast, initializer)
+}
+
+pub fn walk_block_stmt<'src, R, V>(visitor: &mut V, ast: &Ast<'src>, statements: &[StmtId]) -> R
+where
+    R: Default,
+    V: StmtVisitor<'src, R> + ?Sized,
+{
+    visit_stmts(visitor, ast, statements.iter().copied())
+}
+
+/// Visits the condition, then the branches.
+pub fn walk_if_stmt<'src, R, V>(
+    visitor: &mut V,
+    ast: &Ast<'src>,
+    condition: ExprId,
+    then_branch: StmtId,
+    else_branch: Option<StmtId>,
+) -> R
+where
+    R: Default,
+    V: StmtVisitor<'src, R> + ?Sized,
+{
+    let condition = visitor.visit_expr(ast, condition);
+    let branches = visit_stmts(
+        visitor,
+        ast,
+        std::iter::once(then_branch).chain(else_branch),
+    );
+    visitor.combine(condition, branches)
+}
+
+/// Visits the condition, then the body.
+pub fn walk_while_stmt<'src, R, V>(
+    visitor: &mut V,
+    ast: &Ast<'src>,
+    condition: ExprId,
+    body: StmtId,
+) -> R
+where
+    R: Default,
+    V: StmtVisitor<'src, R> + ?Sized,
+{
+    let condition = visitor.visit_expr(ast, condition);
+    let body = visitor.visit_stmt(ast, body);
+    visitor.combine(condition, body)
+}
+
+pub fn walk_function_stmt<'src, R, V>(visitor: &mut V, ast: &Ast<'src>, function: FunctionId) -> R
+where
+    R: Default,
+    V: StmtVisitor<'src, R> + ?Sized,
+{
+    visitor.visit_function(ast, function)
+}
+
+pub fn walk_return_stmt<'src, R, V>(visitor: &mut V, ast: &Ast<'src>, value: Option<ExprId>) -> R
+where
+    R: Default,
+    V: StmtVisitor<'src, R> + ?Sized,
+{
+    visit_exprs(visitor, ast, value)
+}
+
+/// Visits the superclass, then the methods.
+pub fn walk_class_stmt<'src, R, V>(
+    visitor: &mut V,
+    ast: &Ast<'src>,
+    superclass: Option<ExprId>,
+    methods: &[FunctionId],
+) -> R
+where
+    R: Default,
+    V: StmtVisitor<'src, R> + ?Sized,
+{
+    let mut result = visit_exprs(visitor, ast, superclass);
+    for &method in methods {
+        let next = visitor.visit_function(ast, method);
+        result = visitor.combine(result, next);
+    }
+
+    result
+}
+
+/// Visits the statements of the body.
+pub fn walk_function<'src, R, V>(visitor: &mut V, ast: &Ast<'src>, function: FunctionId) -> R
+where
+    R: Default,
+    V: StmtVisitor<'src, R> + ?Sized,
+{
+    visit_stmts(visitor, ast, ast[function].body.iter().copied())
+}
+
+/// Rewriting pass, with a method for each kind like `StmtVisitor`. Nodes are
+/// changed in place through `IndexMut` on the `Ast`, new ones can be added
+/// with `Ast::add_expr` and friends. Side tables keyed by id must be computed
+/// after the rewrite.
+///
+/// The methods get the ids of the node and its children, the rest is read
+/// from `ast` since they may change it. The children are read before being
+/// visited, a visitor replacing a node should do so after walking it.
+pub trait VisitorMut<'src> {
+    /// Calls the method for the kind of `expr`.
+    fn visit_expr_mut(&mut self, ast: &mut Ast<'src>, expr: ExprId) {
+        walk_expr_mut(self, ast, expr)
+    }
+
+    fn visit_literal_mut(&mut self, _ast: &mut Ast<'src>, _expr: ExprId) {}
+
+    fn visit_unary_mut(&mut self, ast: &mut Ast<'src>, _expr: ExprId, right: ExprId) {
+        walk_unary_mut(self, ast, right)
+    }
+
+    fn visit_binary_mut(
+        &mut self,
+        ast: &mut Ast<'src>,
+        _expr: ExprId,
+        left: ExprId,
+        right: ExprId,
+    ) {
+        walk_binary_mut(self, ast, left, right)
+    }
+
+    fn visit_grouping_mut(&mut self, ast: &mut Ast<'src>, _expr: ExprId, inner: ExprId) {
+        walk_grouping_mut(self, ast, inner)
+    }
+
+    fn visit_variable_mut(&mut self, _ast: &mut Ast<'src>, _expr: ExprId) {}
+
+    fn visit_assign_mut(&mut self, ast: &mut Ast<'src>, _expr: ExprId, value: ExprId) {
+        walk_assign_mut(self, ast, value)
+    }
+
+    fn visit_logical_mut(
+        &mut self,
+        ast: &mut Ast<'src>,
+        _expr: ExprId,
+        left: ExprId,
+        right: ExprId,
+    ) {
+        walk_logical_mut(self, ast, left, right)
+    }
+
+    fn visit_call_mut(
+        &mut self,
+        ast: &mut Ast<'src>,
+        _expr: ExprId,
+        callee: ExprId,
+        arguments: &[ExprId],
+    ) {
+        walk_call_mut(self, ast, callee, arguments)
+    }
+
+    fn visit_get_mut(&mut self, ast: &mut Ast<'src>, _expr: ExprId, object: ExprId) {
+        walk_get_mut(self, ast, object)
+    }
+
+    fn visit_set_mut(&mut self, ast: &mut Ast<'src>, _expr: ExprId, object: ExprId, value: ExprId) {
+        walk_set_mut(self, ast, object, value)
+    }
+
+    fn visit_this_mut(&mut self, _ast: &mut Ast<'src>, _expr: ExprId) {}
+
+    fn visit_super_mut(&mut self, _ast: &mut Ast<'src>, _expr: ExprId) {}
+
+    /// Calls the method for the kind of `stmt`.
+    fn visit_stmt_mut(&mut self, ast: &mut Ast<'src>, stmt: StmtId) {
+        walk_stmt_mut(self, ast, stmt)
+    }
+
+    fn visit_expression_stmt_mut(&mut self, ast: &mut Ast<'src>, _stmt: StmtId, expr: ExprId) {
+        walk_expression_stmt_mut(self, ast, expr)
+    }
+
+    fn visit_print_stmt_mut(&mut self, ast: &mut Ast<'src>, _stmt: StmtId, expr: ExprId) {
+        walk_print_stmt_mut(self, ast, expr)
+    }
+
+    fn visit_var_stmt_mut(
+        &mut self,
+        ast: &mut Ast<'src>,
+        _stmt: StmtId,
+        initializer: Option<ExprId>,
+    ) {
+        walk_var_stmt_mut(self, ast, initializer)
+    }
+
+    fn visit_block_stmt_mut(&mut self, ast: &mut Ast<'src>, _stmt: StmtId, statements: &[StmtId]) {
+        walk_block_stmt_mut(self, ast, statements)
+    }
+
+    fn visit_if_stmt_mut(
+        &mut self,
+        ast: &mut Ast<'src>,
+        _stmt: StmtId,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    ) {
+        walk_if_stmt_mut(self, ast, condition, then_branch, else_branch)
+    }
+
+    fn visit_while_stmt_mut(
+        &mut self,
+        ast: &mut Ast<'src>,
+        _stmt: StmtId,
+        condition: ExprId,
+        body: StmtId,
+    ) {
+        walk_while_stmt_mut(self, ast, condition, body)
+    }
+
+    fn visit_function_stmt_mut(
+        &mut self,
+        ast: &mut Ast<'src>,
+        _stmt: StmtId,
+        function: FunctionId,
+    ) {
+        walk_function_stmt_mut(self, ast, function)
+    }
+
+    fn visit_return_stmt_mut(&mut self, ast: &mut Ast<'src>, _stmt: StmtId, value: Option<ExprId>) {
+        walk_return_stmt_mut(self, ast, value)
+    }
+
+    fn visit_class_stmt_mut(
+        &mut self,
+        ast: &mut Ast<'src>,
+        _stmt: StmtId,
+        superclass: Option<ExprId>,
+        methods: &[FunctionId],
+    ) {
+        walk_class_stmt_mut(self, ast, superclass, methods)
+    }
+
+    /// Functions and methods.
+    fn visit_function_mut(&mut self, ast: &mut Ast<'src>, function: FunctionId) {
+        walk_function_mut(self, ast, function)
+    }
+}
+
+/// Calls the method of `visitor` for the kind of `expr`.
+pub fn walk_expr_mut<'src, V>(visitor: &mut V, ast: &mut Ast<'src>, expr: ExprId)
+where
+    V: VisitorMut<'src> + ?Sized,
+{
+    match &ast[expr].kind {
+        ExprKind::Literal(_) => visitor.visit_literal_mut(ast, expr),
+        &ExprKind::Unary(_, right) => visitor.visit_unary_mut(ast, expr, right),
+        &ExprKind::Binary(left, _, right) => visitor.visit_binary_mut(ast, expr, left, right),
+        &ExprKind::Grouping(inner) => visitor.visit_grouping_mut(ast, expr, inner),
+        ExprKind::Variable(_) => visitor.visit_variable_mut(ast, expr),
+        &ExprKind::Assign(_, value) => visitor.visit_assign_mut(ast, expr, value),
+        &ExprKind::Logical(left, _, right) => visitor.visit_logical_mut(ast, expr, left, right),
+        ExprKind::Call(callee, _, arguments) => {
+            let (callee, arguments) = (*callee, arguments.clone());
+            visitor.visit_call_mut(ast, expr, callee, &arguments)
+        }
+        &ExprKind::Get(object, _) => visitor.visit_get_mut(ast, expr, object),
+        &ExprKind::Set(object, _, value) => visitor.visit_set_mut(ast, expr, object, value),
+        ExprKind::This(_) => visitor.visit_this_mut(ast, expr),
+        ExprKind::Super(_, _) => visitor.visit_super_mut(ast, expr),
+    }
+}
+
+pub fn walk_unary_mut<'src, V>(visitor: &mut V, ast: &mut Ast<'src>, right: ExprId)
+where
+    V: VisitorMut<'src> + ?Sized,
+{
+    visitor.visit_expr_mut(ast, right)
+}
+
+pub fn walk_binary_mut<'src, V>(visitor: &mut V, ast: &mut Ast<'src>, left: ExprId, right: ExprId)
+where
+    V: VisitorMut<'src> + ?Sized,
+{
+    visitor.visit_expr_mut(ast, left);
+    visitor.visit_expr_mut(ast, right);
+}
+
+pub fn walk_grouping_mut<'src, V>(visitor: &mut V, ast: &mut Ast<'src>, inner: ExprId)
+where
+    V: VisitorMut<'src> + ?Sized,
+{
+    visitor.visit_expr_mut(ast, inner)
+}
+
+pub fn walk_assign_mut<'src, V>(visitor: &mut V, ast: &mut Ast<'src>, value: ExprId)
+where
+    V: VisitorMut<'src> + ?Sized,
+{
+    visitor.visit_expr_mut(ast, value)
+}
+
+pub fn walk_logical_mut<'src, V>(visitor: &mut V, ast: &mut Ast<'src>, left: ExprId, right: ExprId)
+where
+    V: VisitorMut<'src> + ?Sized,
+{
+    visitor.visit_expr_mut(ast, left);
+    visitor.visit_expr_mut(ast, right);
+}
+
+/// Visits the callee, then the arguments.
+pub fn walk_call_mut<'src, V>(
+    visitor: &mut V,
+    ast: &mut Ast<'src>,
+    callee: ExprId,
+    arguments: &[ExprId],
+) where
+    V: VisitorMut<'src> + ?Sized,
+{
+    visitor.visit_expr_mut(ast, callee);
+    for &argument in arguments {
+        visitor.visit_expr_mut(ast, argument);
+    }
+}
+
+pub fn walk_get_mut<'src, V>(visitor: &mut V, ast: &mut Ast<'src>, object: ExprId)
+where
+    V: VisitorMut<'src> + ?Sized,
+{
+    visitor.visit_expr_mut(ast, object)
+}
+
+/// Visits the object, then the value.
+pub fn walk_set_mut<'src, V>(visitor: &mut V, ast: &mut Ast<'src>, object: ExprId, value: ExprId)
+where
+    V: VisitorMut<'src> + ?Sized,
+{
+    visitor.visit_expr_mut(ast, object);
+    visitor.visit_expr_mut(ast, value);
+}
+
+/// Calls the method of `visitor` for the kind of `stmt`.
+pub fn walk_stmt_mut<'src, V>(visitor: &mut V, ast: &mut Ast<'src>, stmt: StmtId)
+where
+    V: VisitorMut<'src> + ?Sized,
+{
+    match &ast[stmt].kind {
+        &StmtKind::Expression(expr) => visitor.visit_expression_stmt_mut(ast, stmt, expr),
+        &StmtKind::Print(expr) => visitor.visit_print_stmt_mut(ast, stmt, expr),
+        &StmtKind::Var(_, initializer) => visitor.visit_var_stmt_mut(ast, stmt, initializer),
+        StmtKind::Block(statements) => {
+            let statements = statements.clone();
+            visitor.visit_block_stmt_mut(ast, stmt, &statements)
+        }
+        &StmtKind::If(condition, then_branch, else_branch) => {
+            visitor.visit_if_stmt_mut(ast, stmt, condition, then_branch, else_branch)
+        }
+        &StmtKind::While(condition, body) => {
+            visitor.visit_while_stmt_mut(ast, stmt, condition, body)
+        }
+        &StmtKind::Function(function) => visitor.visit_function_stmt_mut(ast, stmt, function),
+        &StmtKind::Return(_, value) => visitor.visit_return_stmt_mut(ast, stmt, value),
+        StmtKind::Class(_, superclass, methods) => {
+            let (superclass, methods) = (*superclass, methods.clone());
+            visitor.visit_class_stmt_mut(ast, stmt, superclass, &methods)
+        }
+    }
+}
+
+pub fn walk_expression_stmt_mut<'src, V>(visitor: &mut V, ast: &mut Ast<'src>, expr: ExprId)
+where
+    V: VisitorMut<'src> + ?Sized,
+{
+    visitor.visit_expr_mut(ast, expr)
+}
+
+pub fn walk_print_stmt_mut<'src, V>(visitor: &mut V, ast: &mut Ast<'src>, expr: ExprId)
+where
+    V: VisitorMut<'src> + ?Sized,
+{
+    visitor.visit_expr_mut(ast, expr)
+}
+
+pub fn walk_var_stmt_mut<'src, V>(visitor: &mut V, ast: &mut Ast<'src>, initializer: Option<ExprId>)
+where
+    V: VisitorMut<'src> + ?Sized,
+{
+    if let Some(initializer) = initializer {
+        visitor.visit_expr_mut(ast, initializer);
+    }
+}
+
+pub fn walk_block_stmt_mut<'src, V>(visitor: &mut V, ast: &mut Ast<'src>, statements: &[StmtId])
+where
+    V: VisitorMut<'src> + ?Sized,
+{
+    for &statement in statements {
+        visitor.visit_stmt_mut(ast, statement);
+    }
+}
+
+/// Visits the condition, then the branches.
+pub fn walk_if_stmt_mut<'src, V>(
+    visitor: &mut V,
+    ast: &mut Ast<'src>,
+    condition: ExprId,
+    then_branch: StmtId,
+    else_branch: Option<StmtId>,
+) where
+    V: VisitorMut<'src> + ?Sized,
+{
+    visitor.visit_expr_mut(ast, condition);
+    visitor.visit_stmt_mut(ast, then_branch);
+    if let Some(else_branch) = else_branch {
+        visitor.visit_stmt_mut(ast, else_branch);
+    }
+}
+
+/// Visits the condition, then the body.
+pub fn walk_while_stmt_mut<'src, V>(
+    visitor: &mut V,
+    ast: &mut Ast<'src>,
+    condition: ExprId,
+    body: StmtId,
+) where
+    V: VisitorMut<'src> + ?Sized,
+{
+    visitor.visit_expr_mut(ast, condition);
+    visitor.visit_stmt_mut(ast, body);
+}
+
+pub fn walk_function_stmt_mut<'src, V>(visitor: &mut V, ast: &mut Ast<'src>, function: FunctionId)
+where
+    V: VisitorMut<'src> + ?Sized,
+{
+    visitor.visit_function_mut(ast, function)
+}
+
+pub fn walk_return_stmt_mut<'src, V>(visitor: &mut V, ast: &mut Ast<'src>, value: Option<ExprId>)
+where
+    V: VisitorMut<'src> + ?Sized,
+{
+    if let Some(value) = value {
+        visitor.visit_expr_mut(ast, value);
+    }
+}
+
+/// Visits the superclass, then the methods.
+pub fn walk_class_stmt_mut<'src, V>(
+    visitor: &mut V,
+    ast: &mut Ast<'src>,
+    superclass: Option<ExprId>,
+    methods: &[FunctionId],
+) where
+    V: VisitorMut<'src> + ?Sized,
+{
+    if let Some(superclass) = superclass {
+        visitor.visit_expr_mut(ast, superclass);
+    }
+    for &method in methods {
+        visitor.visit_function_mut(ast, method);
+    }
+}
+
+/// Visits the statements of the body.
+pub fn walk_function_mut<'src, V>(visitor: &mut V, ast: &mut Ast<'src>, function: FunctionId)
+where
+    V: VisitorMut<'src> + ?Sized,
+{
+    for statement in ast[function].body.clone() {
+        visitor.visit_stmt_mut(ast, statement);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{ast_dump, Parser};
+    use crate::scanner::{Scanner, Token, TokenKind};
+    use crate::symbol::Symbol;
+
+    fn parse(source: &str) -> Ast<'_> {
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens();
+        Parser::new(scanner.tokens).parse().unwrap()
+    }
+
+    /// Counts the nodes it goes through, relying on the default traversal.
+    #[derive(Default)]
+    struct Counter {
+        exprs: usize,
+        stmts: usize,
+    }
+
+    impl<'src> ExprVisitor<'src> for Counter {
+        fn visit_expr(&mut self, ast: &Ast<'src>, expr: ExprId) {
+            self.exprs += 1;
+            walk_expr(self, ast, expr)
+        }
+    }
+
+    impl<'src> StmtVisitor<'src> for Counter {
+        fn visit_stmt(&mut self, ast: &Ast<'src>, stmt: StmtId) {
+            self.stmts += 1;
+            walk_stmt(self, ast, stmt)
+        }
+    }
+
+    #[test]
+    fn walks_every_node() {
+        let ast = parse(
+            "class B < A { m(x) { return super.m(x) or this.y; } }
+             fun f(a) { for (var i = 0; i < a; i = i + 1) if (i) print -i; else g(i, a); }
+             var b = B(); b.c = (1);",
+        );
+
+        let mut counter = Counter::default();
+        for &statement in &ast.statements {
+            counter.visit_stmt(&ast, statement);
+        }
+
+        assert_eq!(ast.expr_count(), counter.exprs);
+        assert_eq!(ast.stmt_count(), counter.stmts);
+    }
+
+    /// Nesting depth of expressions, from the results of the children.
+    struct Depth;
+
+    impl<'src> ExprVisitor<'src, usize> for Depth {
+        fn visit_expr(&mut self, ast: &Ast<'src>, expr: ExprId) -> usize {
+            1 + walk_expr(self, ast, expr)
+        }
+
+        fn combine(&mut self, first: usize, second: usize) -> usize {
+            first.max(second)
+        }
+    }
+
+    impl<'src> StmtVisitor<'src, usize> for Depth {}
+
+    #[test]
+    fn combines_results() {
+        let ast = parse("if (a) print 1 + (2 * -x); else f(1, g(h(2)));");
+        let statement = ast.statements[0];
+
+        assert_eq!(5, Depth.visit_stmt(&ast, statement));
+    }
+
+    /// Renames every variable `a` to `b`.
+    struct Rename;
+
+    impl<'src> VisitorMut<'src> for Rename {
+        fn visit_variable_mut(&mut self, ast: &mut Ast<'src>, expr: ExprId) {
+            if let ExprKind::Variable(name) = &mut ast[expr].kind {
+                if name.lexeme == "a" {
+                    *name = Token::new(TokenKind::Identifier(Symbol::intern("b")), "b", name.line);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rewrite_in_place() {
+        let mut ast = parse("fun f() { return a + f(a, c); }");
+        let statement = ast.statements[0];
+        Rename.visit_stmt_mut(&mut ast, statement);
+
+        let function = match ast[statement].kind {
+            StmtKind::Function(function) => &ast[function],
+            _ => panic!("Expected a function"),
+        };
+        match ast[function.body[0]].kind {
+            StmtKind::Return(_, Some(value)) => {
+                assert_eq!("((b)+((f)((b),(c))))", ast_dump(&ast, value))
+            }
+            _ => panic!("Expected a return statement"),
+        }
+    }
+
+    /// Folds the negation of a number literal into the literal.
+    struct FoldNegation;
+
+    impl<'src> VisitorMut<'src> for FoldNegation {
+        fn visit_unary_mut(&mut self, ast: &mut Ast<'src>, expr: ExprId, right: ExprId) {
+            walk_unary_mut(self, ast, right);
+
+            if let ExprKind::Literal(value) = &ast[right].kind {
+                if let TokenKind::Number(number) = value.kind {
+                    let folded = Token::new(TokenKind::Number(-number), "-", value.line);
+                    ast[expr].kind = ExprKind::Literal(folded);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn replace_node() {
+        let mut ast = parse("print - -2 + -x;");
+        let statement = ast.statements[0];
+        FoldNegation.visit_stmt_mut(&mut ast, statement);
+
+        let left = match ast[statement].kind {
+            StmtKind::Print(value) => match ast[value].kind {
+                ExprKind::Binary(left, _, right) => {
+                    assert_eq!("(-(x))", ast_dump(&ast, right));
+                    left
+                }
+                _ => panic!("Expected a binary expression"),
+            },
+            _ => panic!("Expected a print statement"),
+        };
+        match &ast[left].kind {
+            ExprKind::Literal(value) => assert_eq!(TokenKind::Number(2.0), value.kind),
+            _ => panic!("Expected a literal"),
+        }
+    }
+}
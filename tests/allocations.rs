@@ -0,0 +1,60 @@
+//! Checks that scanning doesn't allocate for each token, with the system
+//! allocator counting what it is asked for. This is its own test binary so
+//! that the allocator of the other tests isn't replaced.
+
+use rlox::scanner::Scanner;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// Counts per thread, the test harness allocates on its own thread while
+/// the test runs.
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // Fails once the thread is being torn down, nothing is measured then.
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const PROGRAM: &str = r#"
+class Shape < Base {
+  describe() {
+    return this.name + " has " + "some sides"; // A comment.
+  }
+}
+/* Block /* nested */ comment. */
+for (var i = 0; i < 10; i = i + 1) print fibonacci(i) * 2.5;
+"#;
+
+/// Allocations made while scanning `source`, and the number of tokens.
+fn scan(source: &str) -> (usize, usize) {
+    let before = ALLOCATIONS.get();
+    let tokens = Scanner::new(source).map(Result::unwrap).count();
+    (ALLOCATIONS.get() - before, tokens)
+}
+
+#[test]
+fn scanning_allocates_once_per_source() {
+    // Interns the identifiers, which only happens the first time.
+    scan(PROGRAM);
+
+    let (once, tokens) = scan(PROGRAM);
+    let (many, many_tokens) = scan(&PROGRAM.repeat(1_000));
+    // A single `Eof` either way.
+    assert_eq!((tokens - 1) * 1_000 + 1, many_tokens);
+    assert_eq!(once, many);
+    assert!(once <= 1, "{} allocations", once);
+}